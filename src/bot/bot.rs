@@ -6,5 +6,14 @@ pub mod callback_query_processor;
 pub mod update_processor;
 pub mod twitter_utils;
 pub mod analytics;
-mod parser;
-mod thread_parser;
\ No newline at end of file
+pub mod tweet_source;
+pub mod twitter_v1_source;
+pub mod twitter_v2_source;
+pub mod fixture_tweet_source;
+pub mod video_uploader;
+pub mod video_policy;
+pub mod chat_settings;
+pub mod parser;
+pub mod thread_parser;
+pub mod rich_text;
+pub mod text_splitter;
\ No newline at end of file
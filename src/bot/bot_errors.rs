@@ -16,10 +16,16 @@ pub enum BotErrorKind {
     TwitterAPIError(egg_mode::error::Error),
     TextParsingError(regex::Error),
     HTMLDecodeError(htmlescape::DecodeErr),
+    JSONParsingError(serde_json::Error),
     CallbackDataParsingError,
     MissedConversationId,
     InvalidThreadResponse,
     MissedUserInTweet,
+    MissedTweet,
+    EmptyThread,
+    VideoTooLarge,
+    InvalidSetting,
+    SettingsNotSaved,
 }
 
 impl From<BotErrorKind> for BotError {
@@ -70,6 +76,12 @@ impl From<htmlescape::DecodeErr> for BotError {
     }
 }
 
+impl From<serde_json::Error> for BotError {
+    fn from(error: serde_json::Error) -> Self {
+        BotError(BotErrorKind::JSONParsingError(error))
+    }
+}
+
 impl fmt::Display for BotError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.0 {
@@ -80,10 +92,16 @@ impl fmt::Display for BotError {
             BotErrorKind::TwitterAPIError(error) => write!(f, "{}", error),
             BotErrorKind::TextParsingError(error) => write!(f, "{}", error),
             BotErrorKind::HTMLDecodeError(_) => write!(f, "HTML decoding error"),
+            BotErrorKind::JSONParsingError(error) => write!(f, "{}", error),
             BotErrorKind::CallbackDataParsingError => write!(f, "Callback data parsing error"),
             BotErrorKind::MissedConversationId => write!(f, "Convesation ID is missed for a thread"), 
             BotErrorKind::InvalidThreadResponse => write!(f, "Invalid thread response"),     
             BotErrorKind::MissedUserInTweet => write!(f, "Missed user in tweet"),           
+            BotErrorKind::MissedTweet => write!(f, "Missed tweet"),
+            BotErrorKind::EmptyThread => write!(f, "No tweets found for a thread"),
+            BotErrorKind::VideoTooLarge => write!(f, "Video is too large to upload"),
+            BotErrorKind::InvalidSetting => write!(f, "Invalid setting"),
+            BotErrorKind::SettingsNotSaved => write!(f, "Chat settings file can't be read, so it isn't overwritten"),
         }
     }
 }
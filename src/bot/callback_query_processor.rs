@@ -1,19 +1,24 @@
 use std::string::String;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 
-use egg_mode::tweet;
 use teloxide::prelude::*;
-use teloxide::types::{ParseMode, InputMediaPhoto, InputFile, InputMedia, InputMediaVideo};
+use teloxide::types::{InputMediaPhoto, InputFile, InputMedia, InputMediaVideo};
 
 use crate::analytics::track_hit;
 use crate::bot_errors::{BotError, BotErrorKind};
-use crate::thread_parser::{tweet_to_thread, ThreadEntity};
-use crate::update_processor::{UpdateProcessor, escaped_text};
-use crate::parser::{tweet_to_reply, Reply, ParsedMedia};
+use crate::chat_settings::ChatSettingsStore;
+use crate::thread_parser::{ThreadEntity, ThreadScope};
+use crate::tweet_source::TweetSource;
+use crate::rich_text::RichText;
+use crate::text_splitter::split_rich_text;
+use crate::update_processor::{UpdateProcessor, caption_layout, max_text_size, rendered, reply_text, CaptionLayout, PARSE_MODE};
+use crate::parser::{Reply, ParsedMedia};
 
 pub struct CallbackQueryProcessor {
-    pub query: CallbackQuery
+    pub query: CallbackQuery,
+    pub settings: Arc<ChatSettingsStore>
 }
 
 #[async_trait]
@@ -22,37 +27,41 @@ impl UpdateProcessor for CallbackQueryProcessor {
         return None
     }
 
-    async fn process(&self, bot: Bot, token: &egg_mode::Token) -> Result<(), BotError> {
+    async fn process(&self, bot: Bot, source: &dyn TweetSource) -> Result<(), BotError> {
         let data = self.data_as_str()?;
 
-        // Processes "Unroll" reply button from a regular text message.
-        if data.starts_with("unroll_") {
-            track_hit(String::from("unroll")).await?;
-            let tweet_id = data.strip_prefix("unroll_").unwrap();
-            let id = tweet_id.parse().unwrap();
-            let tweet = tweet::show(id, &token).await?.response;
-            let reply = tweet_to_thread(&tweet, &token).await?;
-            return self.send_thread_reply(&bot, String::from(tweet_id), reply, false).await;
+        // Processes "Unroll" reply buttons from a regular text message.
+        if let Some(target) = data.strip_prefix("unroll_") {
+            let (id, scope) = ThreadScope::parse_target(target)?;
+            return self.unroll_tweet(bot, id, scope, source).await;
+        } else if data.starts_with("original_") {
+            // Processes "Original Images" reply button.
+            track_hit(String::from("original_images")).await?;
+            let id = data.strip_prefix("original_").unwrap().parse().map_err(|_| BotError::from(BotErrorKind::CallbackDataParsingError))?;
+            let reply = source.reply(id).await?;
+            return self.send_original_images(&bot, &reply).await;
         } else {
             track_hit(String::from("callback")).await?;
-            let id = data.parse().unwrap();
-            let tweet = tweet::show(id, &token).await?.response;
-            let reply = tweet_to_reply(&tweet).await?;
+            let id = data.parse().map_err(|_| BotError::from(BotErrorKind::CallbackDataParsingError))?;
+            let mut reply = source.reply(id).await?;
+            reply.apply_video_policy(&self.video_policy());
+            reply.apply_sensitive_media(self.chat_settings().sensitive_media);
             return self.answer(bot, data, reply, false).await;
         }
     }
 
     async fn answer(&self, bot: Bot, _id: String, reply: Reply, _included_in_thread: bool) -> Result<(), BotError> {
-        let images = reply.media_entities.iter()
+        let images = reply.displayed_media().into_iter()
         .map(|media_entity| {
             match media_entity {
                 ParsedMedia::Image(image) => InputMedia::Photo(InputMediaPhoto {
                     media: InputFile::url(image.url.clone()),
                     caption: None,
                     parse_mode: None,
-                    caption_entities: None
+                    caption_entities: None,
+                    has_spoiler: image.has_spoiler
                 }),
-                ParsedMedia::Video(video) => InputMedia::Video(InputMediaVideo {
+                ParsedMedia::Video(video) | ParsedMedia::Animation(video) => InputMedia::Video(InputMediaVideo {
                     media: InputFile::url(video.url.clone()),
                     thumb: None,
                     caption: None,
@@ -62,37 +71,47 @@ impl UpdateProcessor for CallbackQueryProcessor {
                     height: None,
                     duration: None,
                     supports_streaming: None,
+                    has_spoiler: video.has_spoiler
                 })
             }            
         }).collect::<Vec<_>>();
 
         let chat_id = self.chat_id()?;
+        let layout = caption_layout(&reply_text(&reply, &self.chat_settings()));
+        if let CaptionLayout::TextFirst(text) = &layout {
+            self.send_text(&bot, chat_id, text).await?;
+        }
+
         let messages: Vec<Message> = bot.send_media_group(chat_id.clone(), images).await?;
 
-        if let Some(reply_message) = messages.first() {
+        let caption = layout.caption();
+        if let (Some(reply_message), false) = (messages.first(), caption.is_empty()) {
             bot
             .edit_message_caption(chat_id.clone(), reply_message.id)
-            .caption(escaped_text(&reply))
-            .parse_mode(ParseMode::MarkdownV2)
+            .caption(rendered(&caption))
+            .parse_mode(PARSE_MODE)
             .await?;
         }
 
+        if let CaptionLayout::Split { rest, .. } = &layout {
+            self.send_text(&bot, chat_id, rest).await?;
+        }
         Ok(())
     }
 
-    async fn edit_message_with_thread_entity(&self, bot: &Bot, _entity: &ThreadEntity, escaped_text: &String) -> Result<(), BotError> {
+    async fn edit_message_with_thread_entity(&self, bot: &Bot, _entity: &ThreadEntity, text: &[RichText]) -> Result<(), BotError> {
         if let Some(message) = &self.query.message {
             if let Some(_text) = message.text() {
                 bot
-                .edit_message_text(message.chat.id, message.id, escaped_text)
-                .parse_mode(ParseMode::MarkdownV2)
+                .edit_message_text(message.chat.id, message.id, rendered(text))
+                .parse_mode(PARSE_MODE)
                 .disable_web_page_preview(true)
                 .await?;
             } else if let Some(_capiton) = message.caption() {
                 bot
                 .edit_message_caption(message.chat.id, message.id)
-                .caption(escaped_text)
-                .parse_mode(ParseMode::MarkdownV2)
+                .caption(rendered(text))
+                .parse_mode(PARSE_MODE)
                 .await?;
             }
         }
@@ -109,6 +128,14 @@ impl UpdateProcessor for CallbackQueryProcessor {
         }
         None
     }
+
+    fn settings_store(&self) -> &ChatSettingsStore {
+        &self.settings
+    }
+
+    fn settings_chat_id(&self) -> ChatId {
+        self.message_chat_id().unwrap_or(ChatId(self.query.from.id.0 as i64))
+    }
 }
 
 impl CallbackQueryProcessor {
@@ -124,6 +151,17 @@ impl CallbackQueryProcessor {
         Err(BotError::from(BotErrorKind::CallbackDataParsingError))
     }
 
+    /// Sends text which doesn't fit into the caption of the media group.
+    async fn send_text(&self, bot: &Bot, chat_id: ChatId, text: &[RichText]) -> Result<(), BotError> {
+        for chunk in split_rich_text(text, max_text_size(false)) {
+            bot.send_message(chat_id, rendered(&chunk))
+            .parse_mode(PARSE_MODE)
+            .disable_web_page_preview(true)
+            .await?;
+        }
+        Ok(())
+    }
+
     fn data_as_str(&self) -> Result<String, BotError> {
         match &self.query.data {
             Some(data) => Ok(data.clone()),
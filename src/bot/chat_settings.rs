@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Serialize, Deserialize};
+use teloxide::types::ChatId;
+
+use crate::bot_errors::{BotError, BotErrorKind};
+use crate::video_policy::VideoQuality;
+
+/// What to do with media of tweets marked as possibly sensitive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensitiveMedia {
+    /// Media and text are hidden behind Telegram spoilers.
+    #[default]
+    #[serde(rename = "spoiler")]
+    Spoiler,
+    /// Media is dropped and the text is replaced with a notice.
+    #[serde(rename = "block")]
+    Block,
+    /// Tweets are sent as is.
+    #[serde(rename = "show")]
+    Show,
+}
+
+impl FromStr for SensitiveMedia {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "spoiler" => Ok(SensitiveMedia::Spoiler),
+            "block" => Ok(SensitiveMedia::Block),
+            "show" | "pass-through" => Ok(SensitiveMedia::Show),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for SensitiveMedia {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SensitiveMedia::Spoiler => write!(f, "spoiler"),
+            SensitiveMedia::Block => write!(f, "block"),
+            SensitiveMedia::Show => write!(f, "show")
+        }
+    }
+}
+
+/// Preferences applied to replies in a chat.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChatSettings {
+    pub video_quality: VideoQuality,
+    /// Adds a line with the author handle, the date and a link to the tweet.
+    pub footer: bool,
+    /// Adds a line with likes, retweets, replies and views under the tweet text.
+    pub metrics: bool,
+    /// Also sends tweet polls as closed Telegram polls in direct messages.
+    pub native_polls: bool,
+    /// Also sends images as files in original quality instead of showing a button for that.
+    pub original_images: bool,
+    pub sensitive_media: SensitiveMedia,
+}
+
+impl ChatSettings {
+    /// Returns deployment defaults, e.g. `VIDEO_QUALITY=720p` or `SHOW_FOOTER=on`.
+    pub fn from_env() -> ChatSettings {
+        ChatSettings {
+            video_quality: env_value("VIDEO_QUALITY").unwrap_or_default(),
+            footer: env_switch("SHOW_FOOTER"),
+            metrics: env_switch("SHOW_METRICS"),
+            native_polls: env_switch("NATIVE_POLLS"),
+            original_images: env_switch("ORIGINAL_IMAGES"),
+            sensitive_media: env_value("SENSITIVE_MEDIA").unwrap_or_default(),
+        }
+    }
+
+    /// Returns the settings with the values changed in a chat.
+    pub fn merged(&self, overrides: &ChatSettingsOverrides) -> ChatSettings {
+        ChatSettings {
+            video_quality: overrides.video_quality.unwrap_or(self.video_quality),
+            footer: overrides.footer.unwrap_or(self.footer),
+            metrics: overrides.metrics.unwrap_or(self.metrics),
+            native_polls: overrides.native_polls.unwrap_or(self.native_polls),
+            original_images: overrides.original_images.unwrap_or(self.original_images),
+            sensitive_media: overrides.sensitive_media.unwrap_or(self.sensitive_media),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        [
+            format!("video_quality: {} (best, 1080p, 720p, 480p, 360p)", self.video_quality),
+            format!("footer: {} (on, off)", switch_name(self.footer)),
+            format!("metrics: {} (on, off)", switch_name(self.metrics)),
+            format!("native_polls: {} (on, off)", switch_name(self.native_polls)),
+            format!("original_images: {} (on, off)", switch_name(self.original_images)),
+            format!("sensitive_media: {} (spoiler, block, show)", self.sensitive_media),
+        ].join("\n")
+    }
+}
+
+/// Settings changed in a chat. Missing values fall back to the deployment defaults.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatSettingsOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_quality: Option<VideoQuality>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub footer: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub native_polls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_images: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensitive_media: Option<SensitiveMedia>,
+}
+
+impl ChatSettingsOverrides {
+    /// Updates a setting by its name as used in the `/settings` command.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), BotError> {
+        let invalid = || BotError::from(BotErrorKind::InvalidSetting);
+        match key {
+            "video_quality" => self.video_quality = Some(value.parse().map_err(|_| invalid())?),
+            "footer" => self.footer = Some(parse_switch(value).ok_or_else(invalid)?),
+            "metrics" => self.metrics = Some(parse_switch(value).ok_or_else(invalid)?),
+            "native_polls" => self.native_polls = Some(parse_switch(value).ok_or_else(invalid)?),
+            "original_images" => self.original_images = Some(parse_switch(value).ok_or_else(invalid)?),
+            "sensitive_media" => self.sensitive_media = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(invalid())
+        }
+        Ok(())
+    }
+}
+
+fn env_value<T: FromStr>(key: &str) -> Option<T> {
+    env::var(key).ok().and_then(|value| value.parse().ok())
+}
+
+pub(crate) fn env_switch(key: &str) -> bool {
+    env::var(key).ok().and_then(|value| parse_switch(value.as_str())).unwrap_or(false)
+}
+
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" | "1" => Some(true),
+        "off" | "false" | "no" | "0" => Some(false),
+        _ => None
+    }
+}
+
+fn switch_name(value: bool) -> &'static str {
+    match value {
+        true => "on",
+        false => "off"
+    }
+}
+
+/// Keeps chat settings in memory and stores the changed values as JSON in `CHAT_SETTINGS_PATH` if it's set.
+#[derive(Default)]
+pub struct ChatSettingsStore {
+    defaults: ChatSettings,
+    path: Option<PathBuf>,
+    /// The stored file couldn't be read, so it's kept as is instead of being overwritten.
+    read_only: bool,
+    overrides: Mutex<HashMap<i64, ChatSettingsOverrides>>,
+}
+
+impl ChatSettingsStore {
+    pub fn from_env() -> ChatSettingsStore {
+        let defaults = ChatSettings::from_env();
+        match env::var("CHAT_SETTINGS_PATH") {
+            Ok(path) => ChatSettingsStore::load(PathBuf::from(path), defaults),
+            Err(_) => ChatSettingsStore::with_defaults(defaults)
+        }
+    }
+
+    pub fn with_defaults(defaults: ChatSettings) -> ChatSettingsStore {
+        ChatSettingsStore { defaults, ..ChatSettingsStore::default() }
+    }
+
+    fn load(path: PathBuf, defaults: ChatSettings) -> ChatSettingsStore {
+        let overrides = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(json.as_str()).map_err(BotError::from),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(error) => Err(BotError::from(error))
+        };
+        let read_only = overrides.is_err();
+        if let Err(error) = &overrides {
+            log::error!("Chat settings in {} can't be read, changes won't be saved: {}", path.display(), error);
+        }
+        ChatSettingsStore {
+            defaults,
+            path: Some(path),
+            read_only,
+            overrides: Mutex::new(overrides.unwrap_or_default()),
+        }
+    }
+
+    pub fn get(&self, chat_id: ChatId) -> ChatSettings {
+        match self.overrides.lock().unwrap().get(&chat_id.0) {
+            Some(overrides) => self.defaults.merged(overrides),
+            None => self.defaults.clone()
+        }
+    }
+
+    /// Changes a setting of the chat by its name as used in the `/settings` command.
+    pub fn set(&self, chat_id: ChatId, key: &str, value: &str) -> Result<(), BotError> {
+        if self.read_only {
+            return Err(BotError::from(BotErrorKind::SettingsNotSaved));
+        }
+        let mut overrides = self.overrides.lock().unwrap();
+        let mut chat_overrides = overrides.get(&chat_id.0).cloned().unwrap_or_default();
+        chat_overrides.set(key, value)?;
+        overrides.insert(chat_id.0, chat_overrides);
+        if let Some(path) = &self.path {
+            fs::write(path, serde_json::to_string_pretty(&*overrides)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults(video_quality: VideoQuality) -> ChatSettings {
+        ChatSettings { video_quality, ..ChatSettings::default() }
+    }
+
+    #[test]
+    fn applies_new_defaults_to_unchanged_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let chat_id = ChatId(1);
+        ChatSettingsStore::load(path.clone(), defaults(VideoQuality::Best)).set(chat_id, "footer", "on").unwrap();
+
+        let settings = ChatSettingsStore::load(path.clone(), defaults(VideoQuality::P720)).get(chat_id);
+        assert_eq!(settings, ChatSettings { footer: true, ..defaults(VideoQuality::P720) });
+        assert!(!fs::read_to_string(&path).unwrap().contains("video_quality"));
+    }
+
+    #[test]
+    fn keeps_unreadable_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let store = ChatSettingsStore::load(path.clone(), ChatSettings::default());
+        assert!(store.set(ChatId(1), "footer", "on").is_err());
+        assert_eq!(store.get(ChatId(1)), ChatSettings::default());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ not json");
+    }
+}
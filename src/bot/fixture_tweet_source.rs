@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use async_trait::async_trait;
+use egg_mode::tweet::Tweet;
+use htmlescape::*;
+use reqwest::Url;
+
+use crate::bot_errors::{BotError, BotErrorKind};
+use crate::parser::{tweet_to_reply, Reply};
+use crate::thread_parser::{tweet_to_thread_entity, ThreadReply};
+use crate::tweet_source::TweetSource;
+
+/// Serves recorded v1.1 tweets. Every `*.json` file in the fixture directory contains a single tweet.
+/// Threads are built by following `in_reply_to_status_id` between tweets of the same user.
+pub struct FixtureTweetSource {
+    pub tweets: HashMap<u64, Tweet>
+}
+
+impl FixtureTweetSource {
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> Result<FixtureTweetSource, BotError> {
+        let mut tweets = HashMap::new();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().map(|e| e == "json").unwrap_or(false) {
+                let tweet: Tweet = serde_json::from_str(fs::read_to_string(&path)?.as_str())?;
+                tweets.insert(tweet.id, tweet);
+            }
+        }
+        Ok(FixtureTweetSource { tweets })
+    }
+
+    fn tweet(&self, id: u64) -> Result<&Tweet, BotError> {
+        match self.tweets.get(&id) {
+            Some(tweet) => Ok(tweet),
+            None => Err(BotError::from(BotErrorKind::MissedTweet))
+        }
+    }
+
+    /// Returns tweets of the thread from the head to the last self-reply.
+    fn thread_tweets(&self, id: u64) -> Result<Vec<&Tweet>, BotError> {
+        let mut head = self.tweet(id)?;
+        while let Some(parent) = head.in_reply_to_status_id.and_then(|id| self.tweets.get(&id)) {
+            if user_id(parent) != user_id(head) {
+                break;
+            }
+            head = parent;
+        }
+
+        let mut thread = vec![head];
+        while let Some(next) = self.tweets.values().find(|t| {
+            let last = thread.last().unwrap();
+            t.in_reply_to_status_id == Some(last.id) && user_id(t) == user_id(last)
+        }) {
+            thread.push(next);
+        }
+        Ok(thread)
+    }
+}
+
+fn user_id(tweet: &Tweet) -> Option<u64> {
+    tweet.user.as_ref().map(|user| user.id)
+}
+
+#[async_trait]
+impl TweetSource for FixtureTweetSource {
+    async fn reply(&self, id: u64) -> Result<Reply, BotError> {
+        tweet_to_reply(self.tweet(id)?).await
+    }
+
+    async fn thread(&self, id: u64) -> Result<ThreadReply, BotError> {
+        let tweets = self.thread_tweets(id)?;
+        let user = match tweets[0].user.as_ref() {
+            Some(user) => user,
+            None => return Err(BotError::from(BotErrorKind::MissedUserInTweet))
+        };
+        Ok(ThreadReply {
+            user_name: Some(decode_html(&user.name)?),
+            screen_name: Some(user.screen_name.clone()),
+            verified: user.verified,
+            created_at: Some(tweets[0].created_at),
+            thumb_url: Some(Url::parse(user.profile_image_url_https.as_str())?),
+            entities: tweets.iter().map(|tweet| tweet_to_thread_entity(tweet)).collect::<Vec<_>>(),
+            earlier_tweets_only: false
+        })
+    }
+
+    async fn is_included_in_thread(&self, id: u64) -> Result<bool, BotError> {
+        Ok(self.thread_tweets(id)?.len() >= 2)
+    }
+}
@@ -1,18 +1,22 @@
 use std::string::String;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 
 use teloxide::prelude::*;
-use teloxide::types::{InlineQueryResult, InlineQueryResultArticle, InlineQueryResultVideo, InputMessageContent, InputMessageContentText, ParseMode, InlineQueryResultPhoto};
-use teloxide::utils::markdown::escape;
+use teloxide::types::{InlineQueryResult, InlineQueryResultArticle, InlineQueryResultVideo, InputMessageContent, InputMessageContentText, InlineQueryResultPhoto, InlineQueryResultMpeg4Gif};
 
 use crate::analytics::track_hit;
 use crate::bot_errors::{BotError};
-use crate::update_processor::{UpdateProcessor, escaped_text};
+use crate::chat_settings::ChatSettingsStore;
+use crate::rich_text::{join, RichText};
+use crate::text_splitter::{rich_text_length, split_off_rich_text};
+use crate::update_processor::{UpdateProcessor, escaped_text, max_text_size, rendered, reply_text, text_with_preview, PARSE_MODE};
 use crate::parser::{Reply, ParsedMedia};
 
 pub struct InlineQueryProcessor {
-    pub query: InlineQuery
+    pub query: InlineQuery,
+    pub settings: Arc<ChatSettingsStore>
 }
 
 #[async_trait]
@@ -22,8 +26,11 @@ impl UpdateProcessor for InlineQueryProcessor {
     }
 
     async fn answer(&self, bot: Bot, id: String, reply: Reply, included_in_thread: bool) -> Result<(), BotError> {
-        match reply.media_entities.len() {
-            0 => self.send_text_reply(bot, id, reply, included_in_thread).await,
+        let media = reply.displayed_media();
+        // Inline results can't hide media behind a spoiler or carry a follow-up message, so such tweets are sent as text.
+        let fits_caption = rich_text_length(&reply_text(&reply, &self.chat_settings())) <= max_text_size(true);
+        match (media.len(), media.iter().any(|media| media.has_spoiler()) || !fits_caption) {
+            (0, _) | (_, true) => self.send_text_reply(bot, id, reply, included_in_thread).await,
             _ => self.send_media_reply(bot, id, reply, included_in_thread).await
         }
     }
@@ -31,6 +38,15 @@ impl UpdateProcessor for InlineQueryProcessor {
     async fn track_hit_if_necessary(&self) -> Result<(), BotError> {
         track_hit(String::from("inline")).await
     }
+
+    fn settings_store(&self) -> &ChatSettingsStore {
+        &self.settings
+    }
+
+    /// Inline queries aren't bound to a chat, so the settings of the private chat with the user are used.
+    fn settings_chat_id(&self) -> ChatId {
+        ChatId(self.query.from.id.0 as i64)
+    }
 }
 
 impl InlineQueryProcessor {    
@@ -50,7 +66,7 @@ impl InlineQueryProcessor {
     }
 
     async fn send_media_reply(&self, bot: Bot, id: String, reply: Reply, included_in_thread: bool) -> Result<(), BotError> {  
-        let media_entity_count = reply.media_entities.len();
+        let media_entity_count = reply.displayed_media().len();
 
         let results = self.result_media(id.clone(), reply);
 
@@ -92,7 +108,7 @@ impl InlineQueryProcessor {
         let title: String;
         let description: Option<String>;
         if let Some(user_name) = reply.user_name.clone() {
-            title = escape(user_name.clone().as_str());
+            title = user_name.clone();
             description = Some(reply.text.clone());
         } else {
             title = reply.text.clone();
@@ -102,7 +118,7 @@ impl InlineQueryProcessor {
         return InlineQueryResultArticle {
             id: id,
             title: title,
-            input_message_content: self.message_content(escaped_text(&reply)),
+            input_message_content: self.message_content(text_with_preview(&reply, reply_text(&reply, &self.chat_settings()))),
             reply_markup: None,
             url: None,
             hide_url: None,
@@ -117,14 +133,15 @@ impl InlineQueryProcessor {
         let title: String;
         let description: Option<String>;
         if let Some(user_name) = reply.user_name.clone() {
-            title = escape(user_name.clone().as_str());
+            title = user_name.clone();
             description = Some(reply.text.clone());
         } else {
             title = reply.text.clone();
             description = None;
         }
 
-        reply.media_entities.iter().map(|entity| {
+        let caption = escaped_text(&reply, &self.chat_settings());
+        reply.displayed_media().into_iter().map(|entity| {
             match entity {
                 ParsedMedia::Image(image) => InlineQueryResult::Photo(InlineQueryResultPhoto {
                     id: format!("{}_{}", id, image.id),
@@ -134,8 +151,8 @@ impl InlineQueryProcessor {
                     photo_height: Some(image.height),
                     title: Some(title.clone()),
                     description: description.clone(),
-                    caption: Some(escaped_text(&reply)),
-                    parse_mode: Some(ParseMode::MarkdownV2),
+                    caption: Some(caption.clone()),
+                    parse_mode: Some(PARSE_MODE),
                     caption_entities: None,
                     reply_markup: None,
                     input_message_content: None
@@ -146,8 +163,8 @@ impl InlineQueryProcessor {
                     mime_type: video.mime_type.clone(),
                     thumb_url: video.thumb_url.clone(),
                     title: title.clone(),
-                    parse_mode: Some(ParseMode::MarkdownV2),
-                    caption: Some(escaped_text(&reply)),
+                    parse_mode: Some(PARSE_MODE),
+                    caption: Some(caption.clone()),
                     description: description.clone(),
                     input_message_content: None,
                     reply_markup: None,
@@ -155,6 +172,20 @@ impl InlineQueryProcessor {
                     caption_entities: None,
                     video_width: Some(video.width),
                     video_height: Some(video.height)
+                }),
+                ParsedMedia::Animation(animation) => InlineQueryResult::Mpeg4Gif(InlineQueryResultMpeg4Gif {
+                    id: format!("{}_{}", id, animation.id),
+                    mpeg4_url: animation.url.clone(),
+                    mpeg4_width: Some(animation.width),
+                    mpeg4_height: Some(animation.height),
+                    mpeg4_duration: None,
+                    thumb_url: animation.thumb_url.clone(),
+                    title: Some(title.clone()),
+                    caption: Some(caption.clone()),
+                    parse_mode: Some(PARSE_MODE),
+                    caption_entities: None,
+                    reply_markup: None,
+                    input_message_content: None
                 })
             }
             
@@ -162,12 +193,17 @@ impl InlineQueryProcessor {
         .collect::<Vec<_>>()
     }
 
-    fn message_content(&self, text: String) -> InputMessageContent {
+    /// Returns the text as a single message. An inline result can't send several, so a long text is cut with an ellipsis.
+    fn message_content(&self, (text, disable_preview): (Vec<RichText>, bool)) -> InputMessageContent {
+        let text = match split_off_rich_text(&text, max_text_size(false) - 1) {
+            (head, Some(_)) => join(vec![head, vec![RichText::from("…")]], ""),
+            (head, None) => head
+        };
         return InputMessageContent::Text(InputMessageContentText {
-            message_text: text,
-            parse_mode: Some(ParseMode::MarkdownV2),
+            message_text: rendered(&text),
+            parse_mode: Some(PARSE_MODE),
             entities: None,
-            disable_web_page_preview: None,
+            disable_web_page_preview: Some(disable_preview),
         })        
     }
 }
\ No newline at end of file
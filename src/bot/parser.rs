@@ -1,13 +1,23 @@
+use std::ops::Range;
 use std::string::String;
 
+use chrono::{DateTime, Utc};
 use egg_mode::tweet::Tweet;
-use egg_mode::entities::MediaEntity;
+use egg_mode::entities::{MediaEntity, MediaType};
 use regex::Regex;
 use htmlescape::*;
 use mime;
 use reqwest::Url;
 
 use crate::bot_errors::{BotError, BotErrorKind};
+use crate::chat_settings::SensitiveMedia;
+use crate::rich_text::RichText;
+use crate::video_policy::VideoPolicy;
+
+pub struct VideoVariant {
+    pub url: Url,
+    pub bitrate: Option<i32>
+}
 
 pub struct VideoEntity {
     pub id: String,
@@ -15,30 +25,328 @@ pub struct VideoEntity {
     pub thumb_url: Url,
     pub mime_type: mime::Mime,
     pub width: i32,
-    pub height: i32
+    pub height: i32,
+    pub duration_ms: Option<i32>,
+    /// MP4 variants from the highest bitrate to the lowest.
+    pub variants: Vec<VideoVariant>,
+    pub has_spoiler: bool
+}
+
+impl VideoEntity {
+    /// Switches `url` to the variant chosen by the policy. Higher variants are dropped so
+    /// they aren't downloaded as a fallback either.
+    pub fn apply_policy(&mut self, policy: &VideoPolicy) {
+        if let Some(index) = policy.select(&self.variants, self.duration_ms) {
+            self.variants.drain(..index);
+            self.url = self.variants[0].url.clone();
+        }
+    }
+}
+
+/// Returns a video with the variant chosen by the default policy.
+pub fn video_entity(id: String, thumb_url: Url, width: i32, height: i32, duration_ms: Option<i32>, mut variants: Vec<VideoVariant>) -> Option<VideoEntity> {
+    variants.sort_by_key(|v| -v.bitrate.unwrap_or(0));
+    let url = variants.first()?.url.clone();
+    let mut video = VideoEntity {
+        id,
+        url,
+        thumb_url,
+        mime_type: "video/mp4".parse().unwrap(),
+        width,
+        height,
+        duration_ms,
+        variants,
+        has_spoiler: false
+    };
+    video.apply_policy(&VideoPolicy::default());
+    Some(video)
 }
 
 pub struct ImageEntity {
     pub id: String,
     pub url: Url,
     pub width: i32,
-    pub height: i32
+    pub height: i32,
+    /// Image description provided by the author for screen readers.
+    pub alt_text: Option<String>,
+    pub has_spoiler: bool
+}
+
+impl ImageEntity {
+    /// Returns the URL of the full-resolution file, e.g. `https://pbs.twimg.com/media/<id>.jpg?name=orig`.
+    pub fn original_url(&self) -> Url {
+        let mut url = self.url.clone();
+        let query = url.query_pairs()
+        .filter(|(key, _)| key != "name")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+        url.query_pairs_mut().clear().extend_pairs(query).append_pair("name", "orig");
+        url
+    }
 }
 
 pub enum ParsedMedia {
     Video(VideoEntity),
     Image(ImageEntity),
+    /// An animated GIF, which Twitter delivers as a silent MP4 video.
+    Animation(VideoEntity),
+}
+
+impl ParsedMedia {
+    pub fn has_spoiler(&self) -> bool {
+        match self {
+            ParsedMedia::Image(image) => image.has_spoiler,
+            ParsedMedia::Video(video) | ParsedMedia::Animation(video) => video.has_spoiler
+        }
+    }
+
+    fn set_spoiler(&mut self) {
+        match self {
+            ParsedMedia::Image(image) => image.has_spoiler = true,
+            ParsedMedia::Video(video) | ParsedMedia::Animation(video) => video.has_spoiler = true
+        }
+    }
+}
+
+/// Hides media of a sensitive tweet behind spoilers or drops it.
+pub fn apply_sensitive_media(media_entities: &mut Vec<ParsedMedia>, mode: SensitiveMedia) {
+    match mode {
+        SensitiveMedia::Spoiler => media_entities.iter_mut().for_each(ParsedMedia::set_spoiler),
+        SensitiveMedia::Block => media_entities.clear(),
+        SensitiveMedia::Show => {}
+    }
+}
+
+/// A part of the text shown as a link, e.g. an expanded t.co link, a mention or a hashtag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLink {
+    /// Byte range in the text.
+    pub range: Range<usize>,
+    pub url: Url
+}
+
+/// An entity of the tweet text found by its content, since API indices don't account for HTML escaping.
+pub enum TweetTextEntity {
+    /// A t.co link which isn't displayed, e.g. a link to attached media or to the quoted tweet.
+    Hidden { start: usize, url: String },
+    /// A t.co link replaced with its display URL.
+    Url { start: usize, url: String, display_url: String, expanded_url: String },
+    Mention { start: usize, screen_name: String },
+    Hashtag { start: usize, tag: String },
+}
+
+impl TweetTextEntity {
+    fn start(&self) -> usize {
+        match self {
+            TweetTextEntity::Hidden { start, .. } => *start,
+            TweetTextEntity::Url { start, .. } => *start,
+            TweetTextEntity::Mention { start, .. } => *start,
+            TweetTextEntity::Hashtag { start, .. } => *start,
+        }
+    }
+
+    /// Returns the text to look for.
+    fn pattern(&self) -> String {
+        match self {
+            TweetTextEntity::Hidden { url, .. } => url.clone(),
+            TweetTextEntity::Url { url, .. } => url.clone(),
+            TweetTextEntity::Mention { screen_name, .. } => format!("@{}", screen_name),
+            TweetTextEntity::Hashtag { tag, .. } => format!("#{}", tag),
+        }
+    }
+
+    /// Returns the text displayed instead of the found one if it should be replaced.
+    fn display_text(&self) -> Option<String> {
+        match self {
+            TweetTextEntity::Url { display_url, .. } => Some(display_url.clone()),
+            _ => None
+        }
+    }
+
+    /// Returns the link target. Hidden entities have none and are removed from the text.
+    fn url(&self) -> Option<String> {
+        match self {
+            TweetTextEntity::Hidden { .. } => None,
+            TweetTextEntity::Url { expanded_url, .. } => Some(expanded_url.clone()),
+            TweetTextEntity::Mention { screen_name, .. } => Some(format!("https://twitter.com/{}", screen_name)),
+            TweetTextEntity::Hashtag { tag, .. } => Some(format!("https://twitter.com/hashtag/{}", tag)),
+        }
+    }
+}
+
+/// Engagement counters of a tweet. Replies and views are available with v2 API only.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TweetMetrics {
+    pub likes: i64,
+    pub retweets: i64,
+    pub replies: Option<i64>,
+    pub views: Option<i64>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PollOption {
+    pub label: String,
+    pub votes: i64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Poll {
+    pub options: Vec<PollOption>,
+    pub open: bool,
+    pub end_datetime: Option<DateTime<Utc>>
+}
+
+impl Poll {
+    pub fn total_votes(&self) -> i64 {
+        self.options.iter().map(|option| option.votes).sum()
+    }
+
+    /// Returns the share of votes for the option rounded to a whole percent.
+    pub fn percentage(&self, option: &PollOption) -> i64 {
+        match self.total_votes() {
+            0 => 0,
+            total => (option.votes as f64 * 100.0 / total as f64).round() as i64
+        }
+    }
+}
+
+/// Preview of a link shared in a tweet. Sources without card data only fill the URL.
+pub struct LinkCard {
+    pub url: Url,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// The card image, kept as a media entity so it's sent like a tweet photo.
+    pub image: Option<ParsedMedia>
 }
 
+/// Telegram doesn't allow more items in a media group.
+pub const MAX_MEDIA_GROUP_SIZE: usize = 10;
+
 pub struct Reply {
+    pub id: u64,
     pub user_name: Option<String>,
+    pub screen_name: Option<String>,
+    pub verified: bool,
+    pub created_at: Option<DateTime<Utc>>,
     pub thumb_url: Option<Url>,
     pub text: String,
-    pub media_entities: Vec<ParsedMedia>
+    pub links: Vec<TextLink>,
+    pub media_entities: Vec<ParsedMedia>,
+    /// The tweet quoted by this one.
+    pub quoted: Option<Box<Reply>>,
+    /// Name of the user who retweeted the tweet if the link points to a retweet.
+    pub retweeted_by: Option<String>,
+    pub metrics: Option<TweetMetrics>,
+    pub poll: Option<Poll>,
+    pub card: Option<LinkCard>,
+    /// The tweet is marked as possibly sensitive.
+    pub sensitive: bool,
+    /// The tweet replies to an earlier tweet of its author, i.e. it's in the middle or at the end of a thread.
+    pub continues_thread: bool
+}
+
+impl Reply {
+    pub fn apply_video_policy(&mut self, policy: &VideoPolicy) {
+        apply_video_policy(&mut self.media_entities, policy);
+        if let Some(quoted) = self.quoted.as_mut() {
+            quoted.apply_video_policy(policy);
+        }
+    }
+
+    pub fn apply_sensitive_media(&mut self, mode: SensitiveMedia) {
+        if self.sensitive {
+            apply_sensitive_media(&mut self.media_entities, mode);
+            match (mode, self.card.as_mut()) {
+                (SensitiveMedia::Block, _) => self.card = None,
+                (SensitiveMedia::Spoiler, Some(card)) => card.image.iter_mut().for_each(ParsedMedia::set_spoiler),
+                _ => {}
+            }
+        }
+        if let Some(quoted) = self.quoted.as_mut() {
+            quoted.apply_sensitive_media(mode);
+        }
+    }
+
+    /// Returns media of the tweet followed by media of the quoted tweet as long as they fit into a media group.
+    pub fn displayed_media(&self) -> Vec<&ParsedMedia> {
+        let mut media = self.media_entities.iter().collect::<Vec<_>>();
+        if let Some(quoted) = &self.quoted {
+            media.extend(quoted.media_entities.iter());
+        }
+        if let (true, Some(image)) = (media.is_empty(), self.displayed_card().and_then(|card| card.image.as_ref())) {
+            media.push(image);
+        }
+        media.truncate(MAX_MEDIA_GROUP_SIZE);
+        media
+    }
+
+    /// Returns displayed images of the tweet and the quoted tweet. A link card image isn't included.
+    pub fn displayed_images(&self) -> Vec<&ImageEntity> {
+        if self.displayed_card().is_some() {
+            return vec![];
+        }
+        self.displayed_media().into_iter()
+        .filter_map(|media| match media {
+            ParsedMedia::Image(image) => Some(image),
+            _ => None
+        })
+        .collect()
+    }
+
+    /// Returns the link card if it's the only attachment of the tweet.
+    pub fn displayed_card(&self) -> Option<&LinkCard> {
+        match (&self.quoted, &self.poll, self.media_entities.is_empty()) {
+            (None, None, true) => self.card.as_ref(),
+            _ => None
+        }
+    }
+
+    /// Returns the URL to show a web page preview for. Cards with an image are sent as photos instead.
+    pub fn preview_url(&self) -> Option<&Url> {
+        self.displayed_card().filter(|card| card.image.is_none()).map(|card| &card.url)
+    }
+}
+
+/// Returns alt texts of images in their order. Images without alt text are kept as `None` to preserve numbering.
+pub fn image_alt_texts(media: &[&ParsedMedia]) -> Vec<Option<String>> {
+    media.iter()
+    .filter_map(|media| match media {
+        ParsedMedia::Image(image) => Some(image.alt_text.clone()),
+        _ => None
+    })
+    .collect()
+}
+
+pub fn apply_video_policy(media_entities: &mut [ParsedMedia], policy: &VideoPolicy) {
+    for media in media_entities.iter_mut() {
+        if let ParsedMedia::Video(video) = media {
+            video.apply_policy(policy);
+        }
+    }
 }
 
+/// Matches links to a tweet on twitter.com, x.com and their mobile/www subdomains,
+/// as well as fxtwitter/vxtwitter/fixupx embed mirrors.
+/// Handles `/<user>/status/<id>`, `/i/web/status/<id>` and the legacy `/statuses/<id>` path.
+/// The host has to follow a scheme, `//` or the start of a token, so look-alike hosts such as `notx.com` don't match.
+const TWEET_LINK_REGEX: &str = r#"(?i)(?:^|[\s(\[<"']|https?://|//)(?:(?:www|mobile|m)\.)?(?:twitter|x|fxtwitter|vxtwitter|fixupx|fixvx)\.com/(?:(?:i/web|\w+)/)?status(?:es)?/(\d+)"#;
+
 pub fn tweet_id_from_link(text: &String) -> Result<u64, BotError> {
-    tweet_id(text, r"twitter.com/\w+/status/(\d+)")
+    tweet_id(text, TWEET_LINK_REGEX)
+}
+
+/// Returns IDs of all tweets linked in the text, without duplicates and in order of appearance.
+pub fn tweet_ids_from_links(text: &String) -> Result<Vec<u64>, BotError> {
+    let link_regex = Regex::new(TWEET_LINK_REGEX)?;
+    let mut ids: Vec<u64> = vec![];
+    for caps in link_regex.captures_iter(text) {
+        if let Some(value) = caps.get(1).and_then(|group| group.as_str().parse().ok()) {
+            if !ids.contains(&value) {
+                ids.push(value);
+            }
+        }
+    }
+    Ok(ids)
 }
 
 pub fn tweet_id(text: &String, re: &str) -> Result<u64, BotError> {
@@ -63,32 +371,157 @@ pub fn tweet_id(text: &String, re: &str) -> Result<u64, BotError> {
 }
 
 pub async fn tweet_to_reply(tweet: &Tweet) -> Result<Reply, BotError> {
-    let text = tweet_text_to_displayable_string(&tweet.text);
+    if let Some(original_tweet) = &tweet.retweeted_status {
+        let mut reply = tweet_with_quote_to_reply(original_tweet)?;
+        reply.retweeted_by = tweet.user.as_ref().map(|user| decode_html(&user.name)).transpose()?;
+        return Ok(reply);
+    }
+    tweet_with_quote_to_reply(tweet)
+}
+
+fn tweet_with_quote_to_reply(tweet: &Tweet) -> Result<Reply, BotError> {
+    let mut reply = tweet_to_plain_reply(tweet)?;
+    if let Some(quoted_tweet) = &tweet.quoted_status {
+        reply.quoted = Some(Box::new(tweet_to_plain_reply(quoted_tweet)?));
+    }
+    Ok(reply)
+}
+
+/// Converts the tweet without the tweet it quotes.
+fn tweet_to_plain_reply(tweet: &Tweet) -> Result<Reply, BotError> {
+    let (text, links) = tweet_text_with_links(tweet);
     if let Some(user) = tweet.user.as_ref() {
         let name = decode_html(&user.name)?;
         let thumb_url = Url::parse(user.profile_image_url_https.as_str())?;
         return Ok(Reply {
+            id: tweet.id,
             user_name: Some(name),
+            screen_name: Some(user.screen_name.clone()),
+            verified: user.verified,
+            created_at: Some(tweet.created_at),
             thumb_url: Some(thumb_url),
             text: text,
+            links: links,
             media_entities: tweet_media(&tweet),
+            quoted: None,
+            retweeted_by: None,
+            metrics: Some(tweet_metrics(tweet)),
+            poll: None,
+            card: link_card(tweet),
+            sensitive: tweet.possibly_sensitive.unwrap_or(false),
+            continues_thread: tweet.in_reply_to_status_id.is_some() && tweet.in_reply_to_user_id == Some(user.id),
         });
     } else {
         return Ok(Reply { 
+            id: tweet.id,
             user_name: None,
+            screen_name: None,
+            verified: false,
+            created_at: Some(tweet.created_at),
             thumb_url: None,
             text: text,
+            links: links,
             media_entities: tweet_media(&tweet),
+            quoted: None,
+            retweeted_by: None,
+            metrics: Some(tweet_metrics(tweet)),
+            poll: None,
+            card: link_card(tweet),
+            sensitive: tweet.possibly_sensitive.unwrap_or(false),
+            continues_thread: false,
         });
     }
 }
 
-pub fn tweet_text_to_displayable_string(text: &str) -> String {
+/// Returns a card with the last shared link. v1.1 API doesn't expose card titles and images.
+fn link_card(tweet: &Tweet) -> Option<LinkCard> {
+    if tweet.extended_entities.is_some() {
+        return None;
+    }
+    tweet.entities.urls.iter()
+    .filter_map(|url| url.expanded_url.clone())
+    .rev()
+    .find(|expanded_url| tweet_id_from_link(expanded_url).is_err())
+    .and_then(|expanded_url| Url::parse(expanded_url.as_str()).ok())
+    .map(|url| LinkCard { url, title: None, description: None, image: None })
+}
+
+/// Returns the displayable text of a v1.1 tweet with expanded links, mentions and hashtags.
+pub fn tweet_text_with_links(tweet: &Tweet) -> (String, Vec<TextLink>) {
+    let hidden_ids = [Some(tweet.id), tweet.quoted_status_id];
+    let mut entities = vec![];
+    for url in &tweet.entities.urls {
+        let expanded_url = url.expanded_url.clone().unwrap_or(url.url.clone());
+        let linked_id = tweet_id_from_link(&expanded_url).ok();
+        if linked_id.is_some() && hidden_ids.contains(&linked_id) {
+            entities.push(TweetTextEntity::Hidden { start: url.range.0, url: url.url.clone() });
+        } else {
+            entities.push(TweetTextEntity::Url { start: url.range.0, url: url.url.clone(), display_url: url.display_url.clone(), expanded_url });
+        }
+    }
+    for media in tweet.entities.media.iter().flatten() {
+        entities.push(TweetTextEntity::Hidden { start: media.range.0, url: media.url.clone() });
+    }
+    for mention in &tweet.entities.user_mentions {
+        entities.push(TweetTextEntity::Mention { start: mention.range.0, screen_name: mention.screen_name.clone() });
+    }
+    for hashtag in &tweet.entities.hashtags {
+        entities.push(TweetTextEntity::Hashtag { start: hashtag.range.0, tag: hashtag.text.clone() });
+    }
+    text_with_links(&tweet.text, entities)
+}
+
+/// Decodes the text and replaces entities with links. Entities are searched in order, so a missing one is skipped.
+pub fn text_with_links(text: &str, mut entities: Vec<TweetTextEntity>) -> (String, Vec<TextLink>) {
     let text = decode_html(&text).unwrap_or(String::from(""));
-    if let Ok(trimmed_text) = trim_short_link(&text) {
-        return trimmed_text;
+    // Mentions and hashtags are case-insensitive. ASCII lowercasing keeps byte offsets.
+    let lowercased_text = text.to_ascii_lowercase();
+    entities.sort_by_key(|entity| entity.start());
+
+    let mut result = String::new();
+    let mut links = vec![];
+    let mut cursor = 0;
+    for entity in entities {
+        let pattern = entity.pattern();
+        let start = match lowercased_text[cursor..].find(pattern.to_ascii_lowercase().as_str()) {
+            Some(offset) => cursor + offset,
+            None => continue
+        };
+        let end = start + pattern.len();
+        result.push_str(&text[cursor..start]);
+        if let Some(url) = entity.url() {
+            let display_text = entity.display_text().unwrap_or(String::from(&text[start..end]));
+            if let Ok(url) = Url::parse(url.as_str()) {
+                links.push(TextLink { range: result.len()..result.len() + display_text.len(), url });
+            }
+            result.push_str(display_text.as_str());
+        }
+        cursor = end;
+    }
+    result.push_str(&text[cursor..]);
+
+    let result = trim_short_link(&result).unwrap_or(result);
+    let trimmed_length = result.trim_end().len();
+    links.retain(|link| link.range.end <= trimmed_length);
+    (String::from(&result[..trimmed_length]), links)
+}
+
+fn tweet_metrics(tweet: &Tweet) -> TweetMetrics {
+    TweetMetrics {
+        likes: tweet.favorite_count.into(),
+        retweets: tweet.retweet_count.into(),
+        replies: None,
+        views: None
     }
-    text
+}
+
+/// Returns the link to the tweet. Without the author handle it points to the universal `/i/web/status/` path.
+pub fn tweet_url(id: u64, screen_name: Option<&str>) -> Url {
+    let url = match screen_name {
+        Some(screen_name) => format!("https://twitter.com/{}/status/{}", screen_name, id),
+        None => format!("https://twitter.com/i/web/status/{}", id)
+    };
+    Url::parse(url.as_str()).unwrap()
 }
 
 pub fn trim_short_link(s: &String) -> Result<String, BotError> {
@@ -113,19 +546,15 @@ pub fn tweet_media(tweet: &Tweet) -> Vec<ParsedMedia> {
         if let Ok(thumb_url) = Url::parse(entity.media_url_https.clone().as_str()) {
             let id = format!("{}", entity.id);
             if let Some(info) = &entity.video_info {
-                let mut mp4_variants = info.variants.iter().filter(|v| v.content_type == "video/mp4").collect::<Vec<_>>();
-                mp4_variants.sort_by_key(|v| v.bitrate.unwrap_or(0));
-                if let Some(variant) = mp4_variants.last() {
-                    if let Ok(url) = Url::parse(variant.url.as_str()) {
-                        return Some(ParsedMedia::Video(VideoEntity {
-                            id,
-                            url: url,
-                            thumb_url: thumb_url,
-                            mime_type: variant.content_type.clone(),
-                            width: entity.sizes.large.w,
-                            height: entity.sizes.large.h
-                        }));
-                    }
+                let variants = info.variants.iter()
+                .filter(|v| v.content_type == "video/mp4")
+                .filter_map(|v| Url::parse(v.url.as_str()).ok().map(|url| VideoVariant { url, bitrate: v.bitrate }))
+                .collect::<Vec<_>>();
+                if let Some(video) = video_entity(id.clone(), thumb_url.clone(), entity.sizes.large.w, entity.sizes.large.h, info.duration_millis, variants) {
+                    return match entity.media_type {
+                        MediaType::Gif => Some(ParsedMedia::Animation(video)),
+                        _ => Some(ParsedMedia::Video(video))
+                    };
                 }
             }
 
@@ -133,7 +562,9 @@ pub fn tweet_media(tweet: &Tweet) -> Vec<ParsedMedia> {
                 id,
                 url: thumb_url,
                 width: entity.sizes.large.w,
-                height: entity.sizes.large.h
+                height: entity.sizes.large.h,
+                alt_text: entity.ext_alt_text.clone(),
+                has_spoiler: false
             }));
         }
 
@@ -141,9 +572,78 @@ pub fn tweet_media(tweet: &Tweet) -> Vec<ParsedMedia> {
     }).collect::<Vec<_>>();
 }
 
+/// Turns linked parts of the text into links.
+pub fn rich_text_with_links(text: &str, links: &[TextLink]) -> Vec<RichText> {
+    let mut result = vec![];
+    let mut cursor = 0;
+    for text_link in links {
+        if text_link.range.start < cursor || text_link.range.end > text.len() {
+            continue
+        }
+        result.push(RichText::from(&text[cursor..text_link.range.start]));
+        result.push(RichText::Link(text_link.url.to_string(), vec![RichText::from(&text[text_link.range.clone()])]));
+        cursor = text_link.range.end;
+    }
+    result.push(RichText::from(&text[cursor..]));
+    result
+}
+
 pub trait ReplyData {
     fn user_name(&self) -> Option<String>;
     fn text(&self) -> String;
+
+    fn links(&self) -> Vec<TextLink> {
+        vec![]
+    }
+
+    /// Returns the text with linked parts of it as links.
+    fn rich_text(&self) -> Vec<RichText> {
+        rich_text_with_links(self.text().as_str(), &self.links())
+    }
+
+    fn screen_name(&self) -> Option<String> {
+        None
+    }
+
+    fn verified(&self) -> bool {
+        false
+    }
+
+    fn metrics(&self) -> Option<TweetMetrics> {
+        None
+    }
+
+    fn poll(&self) -> Option<&Poll> {
+        None
+    }
+
+    fn card(&self) -> Option<&LinkCard> {
+        None
+    }
+
+    fn alt_texts(&self) -> Vec<Option<String>> {
+        vec![]
+    }
+
+    fn sensitive(&self) -> bool {
+        false
+    }
+
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    fn tweet_url(&self) -> Option<Url> {
+        None
+    }
+
+    fn quoted(&self) -> Option<&Reply> {
+        None
+    }
+
+    fn retweeted_by(&self) -> Option<String> {
+        None
+    }
 }
 
 impl ReplyData for Reply {
@@ -154,4 +654,167 @@ impl ReplyData for Reply {
     fn text(&self) -> String {
         return self.text.clone();
     }
-}
\ No newline at end of file
+
+    fn links(&self) -> Vec<TextLink> {
+        return self.links.clone();
+    }
+
+    fn screen_name(&self) -> Option<String> {
+        return self.screen_name.clone();
+    }
+
+    fn verified(&self) -> bool {
+        return self.verified;
+    }
+
+    fn metrics(&self) -> Option<TweetMetrics> {
+        return self.metrics.clone();
+    }
+
+    fn poll(&self) -> Option<&Poll> {
+        return self.poll.as_ref();
+    }
+
+    fn card(&self) -> Option<&LinkCard> {
+        return self.displayed_card();
+    }
+
+    fn alt_texts(&self) -> Vec<Option<String>> {
+        return image_alt_texts(&self.displayed_media());
+    }
+
+    fn sensitive(&self) -> bool {
+        return self.sensitive;
+    }
+
+    fn created_at(&self) -> Option<DateTime<Utc>> {
+        return self.created_at;
+    }
+
+    fn tweet_url(&self) -> Option<Url> {
+        return Some(tweet_url(self.id, self.screen_name.as_deref()));
+    }
+
+    fn quoted(&self) -> Option<&Reply> {
+        return self.quoted.as_deref();
+    }
+
+    fn retweeted_by(&self) -> Option<String> {
+        return self.retweeted_by.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(link: &str) -> Option<u64> {
+        tweet_id_from_link(&String::from(link)).ok()
+    }
+
+    #[test]
+    fn recognizes_twitter_links() {
+        assert_eq!(id("https://twitter.com/jack/status/20"), Some(20));
+        assert_eq!(id("http://www.twitter.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://mobile.twitter.com/jack/status/20"), Some(20));
+        assert_eq!(id("twitter.com/jack/status/20"), Some(20));
+    }
+
+    #[test]
+    fn recognizes_x_links() {
+        assert_eq!(id("https://x.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://www.x.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://mobile.x.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://X.com/Jack/status/20"), Some(20));
+    }
+
+    #[test]
+    fn recognizes_alternative_paths() {
+        assert_eq!(id("https://twitter.com/i/web/status/1234567890"), Some(1234567890));
+        assert_eq!(id("https://x.com/i/status/1234567890"), Some(1234567890));
+        assert_eq!(id("https://twitter.com/jack/statuses/1234567890"), Some(1234567890));
+        assert_eq!(id("https://twitter.com/statuses/1234567890"), Some(1234567890));
+        assert_eq!(id("twitter.com/statuses/1234567890"), Some(1234567890));
+        assert_eq!(id("https://twitter.com/jack/status/1234567890/photo/1"), Some(1234567890));
+    }
+
+    #[test]
+    fn recognizes_mirrors() {
+        assert_eq!(id("https://fxtwitter.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://vxtwitter.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://fixupx.com/jack/status/20"), Some(20));
+        assert_eq!(id("https://fixvx.com/jack/status/20"), Some(20));
+    }
+
+    #[test]
+    fn ignores_tracking_query() {
+        assert_eq!(id("https://twitter.com/jack/status/20?s=20&t=AbCdEf-123"), Some(20));
+        assert_eq!(id("https://x.com/jack/status/20?s=46"), Some(20));
+    }
+
+    #[test]
+    fn finds_link_inside_text() {
+        assert_eq!(id("look at this: https://x.com/jack/status/20 wow"), Some(20));
+        assert_eq!(id("(https://x.com/jack/status/20)"), Some(20));
+        assert_eq!(id("look at this: x.com/jack/status/20"), Some(20));
+        assert_eq!(id("<a href=\"//x.com/jack/status/20\">"), Some(20));
+    }
+
+    #[test]
+    fn finds_all_distinct_links() {
+        let text = String::from("https://x.com/a/status/3 and https://twitter.com/b/status/1\nhttps://x.com/a/status/3?s=20 https://fxtwitter.com/c/status/2");
+        assert_eq!(tweet_ids_from_links(&text).unwrap(), vec![3, 1, 2]);
+        assert!(tweet_ids_from_links(&String::from("nothing")).unwrap().is_empty());
+    }
+
+    fn linked_parts(text: &str, links: &[TextLink]) -> Vec<(String, String)> {
+        links.iter().map(|link| (String::from(&text[link.range.clone()]), link.url.to_string())).collect()
+    }
+
+    #[test]
+    fn replaces_entities_with_links() {
+        let entities = vec![
+            TweetTextEntity::Hashtag { start: 30, tag: String::from("rustconf") },
+            TweetTextEntity::Mention { start: 14, screen_name: String::from("rustlang") },
+            TweetTextEntity::Url { start: 50, url: String::from("https://t.co/a1"), display_url: String::from("rust-lang.org"), expanded_url: String::from("https://www.rust-lang.org/") },
+            TweetTextEntity::Hidden { start: 70, url: String::from("https://t.co/b2") },
+        ];
+        let (text, links) = text_with_links("Big thanks to @RustLang &amp; #RustConf 🦀 https://t.co/a1 https://t.co/b2", entities);
+        assert_eq!(text, "Big thanks to @RustLang & #RustConf 🦀 rust-lang.org");
+        assert_eq!(linked_parts(&text, &links), vec![
+            (String::from("@RustLang"), String::from("https://twitter.com/rustlang")),
+            (String::from("#RustConf"), String::from("https://twitter.com/hashtag/rustconf")),
+            (String::from("rust-lang.org"), String::from("https://www.rust-lang.org/")),
+        ]);
+    }
+
+    #[test]
+    fn skips_missing_entities() {
+        let entities = vec![
+            TweetTextEntity::Mention { start: 0, screen_name: String::from("nobody") },
+            TweetTextEntity::Hashtag { start: 5, tag: String::from("rust") },
+        ];
+        let (text, links) = text_with_links("Hi #rust https://t.co/c3", entities);
+        assert_eq!(text, "Hi #rust");
+        assert_eq!(linked_parts(&text, &links), vec![(String::from("#rust"), String::from("https://twitter.com/hashtag/rust"))]);
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        assert_eq!(id("https://example.com/jack/status/20"), None);
+        assert_eq!(id("https://box.com/jack/status/20"), None);
+        assert_eq!(id("https://my-x.com/jack/status/20"), None);
+        assert_eq!(id("https://notx.com/jack/status/20"), None);
+        assert_eq!(id("https://example.com/x.com/jack/status/20"), None);
+        assert_eq!(id("https://twitter.com/jack"), None);
+        assert_eq!(id("https://x.com/jack/likes"), None);
+        assert_eq!(id("no links here"), None);
+    }
+
+    #[test]
+    fn requests_original_image_quality() {
+        let image = |url: &str| ImageEntity { id: String::from("1"), url: Url::parse(url).unwrap(), width: 1, height: 1, alt_text: None, has_spoiler: false };
+        assert_eq!(image("https://pbs.twimg.com/media/F1.jpg").original_url().as_str(), "https://pbs.twimg.com/media/F1.jpg?name=orig");
+        assert_eq!(image("https://pbs.twimg.com/media/F1?format=png&name=small").original_url().as_str(), "https://pbs.twimg.com/media/F1?format=png&name=orig");
+    }
+}
@@ -0,0 +1,132 @@
+use teloxide::types::ParseMode;
+use teloxide::utils::{html, markdown};
+
+/// Formatted text which is turned into markup only when sent.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RichText {
+    Plain(String),
+    Bold(Vec<RichText>),
+    Italic(Vec<RichText>),
+    Link(String, Vec<RichText>),
+    /// A block with a bar before every line.
+    Quote(Vec<RichText>),
+    Spoiler(Vec<RichText>),
+    Code(String),
+}
+
+impl From<&str> for RichText {
+    fn from(text: &str) -> Self {
+        RichText::Plain(String::from(text))
+    }
+}
+
+impl From<String> for RichText {
+    fn from(text: String) -> Self {
+        RichText::Plain(text)
+    }
+}
+
+impl RichText {
+    pub fn to_html(&self) -> String {
+        match self {
+            RichText::Plain(text) => html::escape(text),
+            RichText::Bold(children) => format!("<b>{}</b>", to_html(children)),
+            RichText::Italic(children) => format!("<i>{}</i>", to_html(children)),
+            RichText::Link(url, children) => format!("<a href=\"{}\">{}</a>", html::escape(url).replace('"', "&quot;"), to_html(children)),
+            RichText::Quote(children) => with_bars(&to_html(children)),
+            RichText::Spoiler(children) => format!("<tg-spoiler>{}</tg-spoiler>", to_html(children)),
+            RichText::Code(text) => html::code_inline(text),
+        }
+    }
+
+    pub fn to_markdown_v2(&self) -> String {
+        match self {
+            RichText::Plain(text) => markdown::escape(text),
+            RichText::Bold(children) => format!("*{}*", to_markdown_v2(children)),
+            RichText::Italic(children) => format!("_{}_", to_markdown_v2(children)),
+            RichText::Link(url, children) => markdown::link(url, &to_markdown_v2(children)),
+            RichText::Quote(children) => with_bars(&to_markdown_v2(children)),
+            RichText::Spoiler(children) => format!("||{}||", to_markdown_v2(children)),
+            RichText::Code(text) => markdown::code_inline(text),
+        }
+    }
+
+    /// Returns the text as it's displayed, without formatting.
+    pub fn to_plain_text(&self) -> String {
+        match self {
+            RichText::Plain(text) | RichText::Code(text) => text.clone(),
+            RichText::Bold(children) | RichText::Italic(children) | RichText::Spoiler(children) | RichText::Link(_, children) => to_plain_text(children),
+            RichText::Quote(children) => with_bars(&to_plain_text(children)),
+        }
+    }
+}
+
+pub fn to_html(text: &[RichText]) -> String {
+    text.iter().map(RichText::to_html).collect()
+}
+
+pub fn to_markdown_v2(text: &[RichText]) -> String {
+    text.iter().map(RichText::to_markdown_v2).collect()
+}
+
+pub fn to_plain_text(text: &[RichText]) -> String {
+    text.iter().map(RichText::to_plain_text).collect()
+}
+
+/// Renders the text as markup of the parse mode. The legacy Markdown mode isn't supported and gets MarkdownV2.
+pub fn render(text: &[RichText], parse_mode: ParseMode) -> String {
+    match parse_mode {
+        ParseMode::Html => to_html(text),
+        _ => to_markdown_v2(text)
+    }
+}
+
+/// Joins parts of the text with a plain separator, e.g. paragraphs with an empty line.
+pub fn join(parts: Vec<Vec<RichText>>, separator: &str) -> Vec<RichText> {
+    let mut text = vec![];
+    for (index, part) in parts.into_iter().enumerate() {
+        if index > 0 {
+            text.push(RichText::from(separator));
+        }
+        text.extend(part);
+    }
+    text
+}
+
+fn with_bars(text: &str) -> String {
+    text.lines()
+    .map(|line| format!("┃ {}", line).trim_end().to_string())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<RichText> {
+        vec![
+            RichText::Bold(vec![RichText::from("Rust & <Friends>")]),
+            RichText::from(" (1.65) "),
+            RichText::Link(String::from("https://example.com/a_(b)?c=\"d\""), vec![RichText::Italic(vec![RichText::from("docs_page")])]),
+            RichText::from("\n"),
+            RichText::Quote(vec![RichText::Spoiler(vec![RichText::from("first\nsecond")]), RichText::from(" "), RichText::Code(String::from("a<b>`c`"))]),
+        ]
+    }
+
+    #[test]
+    fn renders_html() {
+        assert_eq!(to_html(&sample()), "<b>Rust &amp; &lt;Friends&gt;</b> (1.65) <a href=\"https://example.com/a_(b)?c=&quot;d&quot;\"><i>docs_page</i></a>\n┃ <tg-spoiler>first\n┃ second</tg-spoiler> <code>a&lt;b&gt;`c`</code>");
+    }
+
+    #[test]
+    fn renders_markdown_v2() {
+        assert_eq!(to_markdown_v2(&sample()), "*Rust & <Friends\\>* \\(1\\.65\\) [_docs\\_page_](https://example.com/a_(b\\)?c=\"d\")\n┃ ||first\n┃ second|| `a<b>\\`c\\``");
+    }
+
+    #[test]
+    fn renders_plain_text() {
+        assert_eq!(to_plain_text(&sample()), "Rust & <Friends> (1.65) docs_page\n┃ first\n┃ second a<b>`c`");
+        assert_eq!(render(&sample(), ParseMode::Html), to_html(&sample()));
+    }
+}
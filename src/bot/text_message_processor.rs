@@ -1,19 +1,28 @@
 use std::convert::TryInto;
+use std::env;
 use std::string::String;
+use std::sync::Arc;
 use async_trait::async_trait;
 
 use teloxide::prelude::*;
-use teloxide::types::{InputFile, InputMedia, InputMediaPhoto, ParseMode, InlineKeyboardMarkup, InlineKeyboardButton, InputMediaVideo};
-use teloxide::utils::markdown::{bold, escape};
+use teloxide::types::{InputFile, InputMedia, InputMediaPhoto, InlineKeyboardMarkup, InlineKeyboardButton, InputMediaVideo, MessageEntityKind};
 
 use crate::analytics::track_hit;
-use crate::update_processor::{UpdateProcessor, escaped_text};
+use crate::rich_text::{join, RichText};
+use crate::text_splitter::{split_rich_text, text_length};
+use crate::update_processor::{UpdateProcessor, max_text_size, rendered, reply_text, text_with_preview, PARSE_MODE};
 use crate::bot_errors::BotError;
-use crate::parser::{tweet_id_from_link, tweet_id, Reply, ParsedMedia, VideoEntity, ImageEntity}; 
+use crate::chat_settings::{ChatSettingsOverrides, ChatSettingsStore};
+use crate::tweet_source::TweetSource;
+use crate::parser::{tweet_ids_from_links, tweet_id, Reply, ParsedMedia, Poll};
+use crate::thread_parser::ThreadScope;
 
 pub struct TextMessageProcessor {
     pub message: Message,
-    pub text: String
+    pub text: String,
+    pub settings: Arc<ChatSettingsStore>,
+    /// Username of the bot, which group commands are addressed to, e.g. `/settings@<bot_name>`.
+    pub bot_name: String
 }
 
 #[async_trait]
@@ -22,34 +31,48 @@ impl UpdateProcessor for TextMessageProcessor {
         Some(&self.text)
     }
 
-    async fn process(&self, bot: Bot, token: &egg_mode::Token) -> Result<(), BotError> {
-        if self.text.as_str().starts_with("/start") {
-            if self.text.as_str().starts_with("/start unroll_") {
-                let id = self.text.as_str().strip_prefix("/start unroll_").unwrap().parse().unwrap();
-                self.unroll_tweet(bot, id, token).await
+    async fn process(&self, bot: Bot, source: &dyn TweetSource) -> Result<(), BotError> {
+        if let Some(arguments) = self.command_arguments("/settings") {
+            self.process_settings_command(bot, arguments).await
+        } else if self.text.as_str().starts_with("/start") {
+            if let Some(target) = self.text.as_str().strip_prefix("/start unroll_") {
+                let (id, scope) = ThreadScope::parse_target(target)?;
+                self.unroll_tweet(bot, id, scope, source).await
             } else {
                 match self.tweet_id_from_deeplink(&self.text) {
-                    Ok(id) => self.process_tweet(bot, id, token).await,
+                    Ok(id) => self.process_tweet(bot, id, source).await,
                     _ => self.send_info_message(bot).await
                 }
             }
         } else {
-            match tweet_id_from_link(&self.text) {
-                Ok(id) => self.process_tweet(bot, id, token).await,
-                _ => Ok(())
-            }            
+            let ids = tweet_ids_from_links(&self.text_with_links())?;
+            for id in ids.into_iter().take(max_tweets_per_message()) {
+                if let Err(error) = self.process_tweet(bot.clone(), id, source).await {
+                    log::error!("Failed to process tweet {}: {}", id, error);
+                }
+            }
+            Ok(())
         }
     }
 
     async fn answer(&self, bot: Bot, id: String, reply: Reply, included_in_thread: bool) -> Result<(), BotError> {
-        match reply.media_entities.len() {
-            0 => self.send_text_reply(bot, id, &reply, included_in_thread).await,
-            1 => match reply.media_entities.first().unwrap() {
-                ParsedMedia::Image(image) => self.send_image_reply(bot, id, &reply, image, included_in_thread).await,
-                ParsedMedia::Video(video) => self.send_video_reply(bot, id, &reply, video, included_in_thread).await,
+        let media = reply.displayed_media();
+        match media.len() {
+            0 => self.send_text_reply(bot.clone(), id, &reply, included_in_thread).await?,
+            1 => {
+                let keyboard = self.make_keyboard(&id, &reply, included_in_thread);
+                self.send_media_with_text(&bot, media[0], &reply_text(&reply, &self.chat_settings()), keyboard).await?
             },
-            _ => self.send_media_group_reply(bot, id, &reply, included_in_thread).await
+            _ => self.send_media_group_reply(bot.clone(), id, &reply, included_in_thread).await?
+        }
+        if let (Some(poll), true) = (&reply.poll, self.chat_settings().native_polls && self.message.chat.is_private()) {
+            self.send_native_poll(bot.clone(), &reply, poll).await?;
+        }
+        // Files can't be hidden behind a spoiler, so originals of sensitive tweets are only sent on request.
+        if self.chat_settings().original_images && !reply.sensitive {
+            self.send_original_images(&bot, &reply).await?;
         }
+        Ok(())
     }
 
     async fn track_hit_if_necessary(&self) -> Result<(), BotError> {
@@ -59,63 +82,53 @@ impl UpdateProcessor for TextMessageProcessor {
     fn message_chat_id(&self) -> Option<ChatId> {
         Some(self.message.chat.id)
     }
+
+    fn settings_store(&self) -> &ChatSettingsStore {
+        &self.settings
+    }
+
+    fn settings_chat_id(&self) -> ChatId {
+        self.message.chat.id
+    }
 }
 
 impl TextMessageProcessor {
     async fn send_text_reply(&self, bot: Bot, id: String, reply: &Reply, included_in_thread: bool) -> Result<(), BotError> {
-        let mut request = bot.send_message(self.message.chat.id, escaped_text(reply))
-        .parse_mode(ParseMode::MarkdownV2)
-        .disable_web_page_preview(true);
-
-        if included_in_thread {
-            request = request.reply_markup(self.make_keyboard(&id));
-        }
-
-        request.await?;
-        Ok(())
+        let (text, disable_preview) = text_with_preview(reply, reply_text(reply, &self.chat_settings()));
+        self.send_text_chunks(&bot, &text, disable_preview, self.make_keyboard(&id, reply, included_in_thread)).await
     }
 
-    async fn send_image_reply(&self, bot: Bot, id: String, reply: &Reply, image: &ImageEntity, included_in_thread: bool) -> Result<(), BotError> {
-        let video = InputFile::url(image.url.clone());
-        let mut request = bot.send_photo(self.message.chat.id, video)
-        .caption(escaped_text(reply))
-        .parse_mode(ParseMode::MarkdownV2); 
-
-        if included_in_thread {
-            request = request.reply_markup(self.make_keyboard(&id));
-        }
-        
-        request.await?;
+    /// Sends the text split into messages Telegram accepts. Only the first one may have a web page preview,
+    /// and the keyboard goes to the last one.
+    async fn send_text_chunks(&self, bot: &Bot, text: &[RichText], disable_preview: bool, keyboard: Option<InlineKeyboardMarkup>) -> Result<(), BotError> {
+        let chunks = split_rich_text(text, max_text_size(false));
+        let count = chunks.len();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let mut request = bot.send_message(self.message.chat.id, rendered(&chunk))
+            .parse_mode(PARSE_MODE)
+            .disable_web_page_preview(disable_preview || index > 0);
 
-        Ok(())
-    }
-
-    async fn send_video_reply(&self, bot: Bot, id: String, reply: &Reply, video: &VideoEntity, included_in_thread: bool) -> Result<(), BotError> {
-        let video = InputFile::url(video.url.clone());
-        let mut request = bot.send_video(self.message.chat.id, video)
-        .caption(escaped_text(reply))
-        .parse_mode(ParseMode::MarkdownV2); 
+            if let (true, Some(keyboard)) = (index + 1 == count, keyboard.clone()) {
+                request = request.reply_markup(keyboard);
+            }
 
-        if included_in_thread {
-            request = request.reply_markup(self.make_keyboard(&id));
+            request.await?;
         }
-        
-        request.await?;
-
         Ok(())
     }
 
     async fn send_media_group_reply(&self, bot: Bot, id: String, reply: &Reply, included_in_thread: bool) -> Result<(), BotError> {
-        let group = reply.media_entities.iter()
+        let group = reply.displayed_media().into_iter()
         .map(|media_entity| {
             match media_entity {
                 ParsedMedia::Image(image) => InputMedia::Photo(InputMediaPhoto {
                     media: InputFile::url(image.url.clone()),
                     caption: None,
                     parse_mode: None,
-                    caption_entities: None
+                    caption_entities: None,
+                    has_spoiler: image.has_spoiler
                 }),
-                ParsedMedia::Video(video) => InputMedia::Video(InputMediaVideo {
+                ParsedMedia::Video(video) | ParsedMedia::Animation(video) => InputMedia::Video(InputMediaVideo {
                     media: InputFile::url(video.url.clone()),
                     thumb: None,
                     caption: None,
@@ -125,6 +138,7 @@ impl TextMessageProcessor {
                     height: Some(video.height.try_into().unwrap()),
                     duration: None,
                     supports_streaming: None,
+                    has_spoiler: video.has_spoiler
                 })
             }            
         }).collect::<Vec<_>>();
@@ -132,35 +146,176 @@ impl TextMessageProcessor {
         let chat_id = self.message.chat.id;
         bot.send_media_group(chat_id, group).await?;
 
-        let mut request = bot.send_message(self.message.chat.id, escaped_text(reply))
-        .parse_mode(ParseMode::MarkdownV2)
-        .disable_web_page_preview(true);
-
-        if included_in_thread {
-            request = request.reply_markup(self.make_keyboard(&id));
-        }
+        self.send_text_chunks(&bot, &reply_text(reply, &self.chat_settings()), true, self.make_keyboard(&id, reply, included_in_thread)).await
+    }
 
-        request.await?;
+    /// Sends the tweet poll as a closed anonymous Telegram poll. Votes of the tweet poll can't be transferred.
+    async fn send_native_poll(&self, bot: Bot, reply: &Reply, poll: &Poll) -> Result<(), BotError> {
+        let question = poll_question(reply.text.as_str());
+        let options = poll.options.iter()
+        .map(|option| truncated(option.label.as_str(), MAX_POLL_OPTION_LENGTH))
+        .collect::<Vec<_>>();
+        bot.send_poll(self.message.chat.id, question, options)
+        .is_anonymous(true)
+        .is_closed(true)
+        .await?;
         Ok(())
     }
 
+    /// Returns the message text followed by URLs of its text links, one per line.
+    fn text_with_links(&self) -> String {
+        let mut text = self.text.clone();
+        if let Some(entities) = self.message.entities() {
+            for entity in entities {
+                if let MessageEntityKind::TextLink { url } = &entity.kind {
+                    text = format!("{}\n{}", text, url);
+                }
+            }
+        }
+        text
+    }
+
     fn tweet_id_from_deeplink(&self, text: &String) -> Result<u64, BotError> {
         tweet_id(text, r"/start (\d+)")
     }
 
+    /// Returns arguments of the command if the message is exactly this command, optionally addressed to the bot.
+    fn command_arguments(&self, command: &str) -> Option<Vec<&str>> {
+        let mut words = self.text.split_whitespace();
+        let first_word = words.next()?;
+        let name = match first_word.split_once('@') {
+            Some((name, bot_name)) if bot_name.eq_ignore_ascii_case(self.bot_name.as_str()) => name,
+            Some(_) => return None,
+            None => first_word
+        };
+        match name == command {
+            true => Some(words.collect::<Vec<_>>()),
+            false => None
+        }
+    }
+
+    /// Anyone can view the settings, but in groups only administrators can change them.
+    async fn can_change_settings(&self, bot: &Bot) -> Result<bool, BotError> {
+        if self.message.chat.is_private() {
+            return Ok(true);
+        }
+        // Anonymous administrators send messages on behalf of the group.
+        if self.message.sender_chat().map(|chat| chat.id) == Some(self.message.chat.id) {
+            return Ok(true);
+        }
+        match self.message.from() {
+            Some(user) => Ok(bot.get_chat_member(self.message.chat.id, user.id).await?.is_privileged()),
+            None => Ok(false)
+        }
+    }
+
+    /// Processes `/settings` to show the chat settings and `/settings <name> <value>` to change one.
+    async fn process_settings_command(&self, bot: Bot, arguments: Vec<&str>) -> Result<(), BotError> {
+        let title = match arguments.as_slice() {
+            [_, _] if !self.can_change_settings(&bot).await? => "Only administrators can change settings",
+            [key, value] if ChatSettingsOverrides::default().set(key, value).is_err() => "Unknown setting or value",
+            [key, value] => match self.settings.set(self.message.chat.id, key, value) {
+                Ok(_) => "Settings updated",
+                Err(error) => {
+                    log::error!("Failed to save settings: {}", error);
+                    "Settings can't be saved now"
+                }
+            },
+            _ => "Settings"
+        };
+        let settings = self.chat_settings();
+        let usage = "Change a setting with /settings <name> <value>";
+        let text = join(vec![vec![RichText::Bold(vec![RichText::from(title)])], vec![RichText::from(settings.description())], vec![RichText::from(usage)]], "\n\n");
+        bot.send_message(self.message.chat.id, rendered(&text)).parse_mode(PARSE_MODE).await?;
+        Ok(())
+    }
+
     async fn send_info_message(&self, bot: Bot) -> Result<(), BotError> {
         let info_text = "This bot allows you to convert tweet links to regular Telegram messages. It can download videos and images from tweets. And also it can unroll threads. Just send a link herr or address @twt2tgbot in any chat.";
-        let text = format!("{}\n\n{}", bold(escape("What can this bot do?").as_str()), escape(info_text));
-        bot.send_message(self.message.chat.id, text).parse_mode(ParseMode::MarkdownV2).await?;
+        let text = join(vec![vec![RichText::Bold(vec![RichText::from("What can this bot do?")])], vec![RichText::from(info_text)]], "\n\n");
+        bot.send_message(self.message.chat.id, rendered(&text)).parse_mode(PARSE_MODE).await?;
         Ok(())
     }
     
-    fn make_keyboard(&self, tweet_id: &String) -> InlineKeyboardMarkup {
-        let unroll_button = InlineKeyboardButton::callback(
-            String::from("Unroll Thread"),
-            format!("unroll_{}", tweet_id.clone())
-        );
-        let keyboard: Vec<Vec<InlineKeyboardButton>> = vec![vec![unroll_button]];    
-        InlineKeyboardMarkup::new(keyboard)
-    }
-}
\ No newline at end of file
+    /// Returns buttons to unroll the thread and to get images in original quality unless they're sent anyway.
+    /// A tweet in the middle of a thread can be unrolled either with the whole thread or from this tweet onward.
+    fn make_keyboard(&self, tweet_id: &str, reply: &Reply, included_in_thread: bool) -> Option<InlineKeyboardMarkup> {
+        let mut keyboard: Vec<Vec<InlineKeyboardButton>> = vec![];
+        if included_in_thread && reply.continues_thread {
+            keyboard.push(vec![
+                InlineKeyboardButton::callback(String::from("Whole Thread"), format!("unroll_{}", tweet_id)),
+                InlineKeyboardButton::callback(String::from("From This Tweet"), format!("unroll_from_{}", tweet_id))
+            ]);
+        } else if included_in_thread {
+            keyboard.push(vec![InlineKeyboardButton::callback(
+                String::from("Unroll Thread"),
+                format!("unroll_{}", tweet_id)
+            )]);
+        }
+        if !reply.displayed_images().is_empty() && !self.chat_settings().original_images {
+            keyboard.push(vec![InlineKeyboardButton::callback(
+                String::from("Original Images"),
+                format!("original_{}", tweet_id)
+            )]);
+        }
+        match keyboard.is_empty() {
+            true => None,
+            false => Some(InlineKeyboardMarkup::new(keyboard))
+        }
+    }
+}
+
+/// Telegram limits for poll questions and options.
+const MAX_POLL_QUESTION_LENGTH: usize = 300;
+const MAX_POLL_OPTION_LENGTH: usize = 100;
+
+/// Telegram rejects an empty question, e.g. of a poll tweet with only links in its text.
+fn poll_question(text: &str) -> String {
+    match text.trim().is_empty() {
+        true => String::from("Poll"),
+        false => truncated(text, MAX_POLL_QUESTION_LENGTH)
+    }
+}
+
+/// Cuts the text to `max_length` UTF-16 code units, which Telegram counts the limits in.
+fn truncated(text: &str, max_length: usize) -> String {
+    if text_length(text) <= max_length {
+        return String::from(text);
+    }
+    let mut length = 0;
+    let head = text.chars()
+    .take_while(|c| {
+        length += c.len_utf16();
+        length < max_length
+    })
+    .collect::<String>();
+    format!("{}…", head)
+}
+
+/// Maximum number of tweets converted from a single message. Configured with `MAX_TWEETS_PER_MESSAGE`.
+pub fn max_tweets_per_message() -> usize {
+    env::var("MAX_TWEETS_PER_MESSAGE").ok()
+    .and_then(|value| value.parse().ok())
+    .unwrap_or(5)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_fixed_poll_question() {
+        assert_eq!(poll_question(""), "Poll");
+        assert_eq!(poll_question(" \n"), "Poll");
+        assert_eq!(poll_question("Which feature?"), "Which feature?");
+    }
+
+    #[test]
+    fn truncates_poll_text_in_utf16_units() {
+        let text = "🦀".repeat(60);
+        let option = truncated(text.as_str(), MAX_POLL_OPTION_LENGTH);
+        assert_eq!(option, format!("{}…", "🦀".repeat(49)));
+        assert!(text_length(option.as_str()) <= MAX_POLL_OPTION_LENGTH);
+        assert_eq!(truncated("GATs", MAX_POLL_OPTION_LENGTH), "GATs");
+    }
+}
@@ -0,0 +1,374 @@
+use std::ops::Range;
+
+use crate::rich_text::{to_plain_text, RichText};
+
+/// Telegram measures text length in UTF-16 code units.
+pub fn text_length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Length of the text as Telegram limits it: without formatting markup.
+pub fn rich_text_length(text: &[RichText]) -> usize {
+    text_length(&to_plain_text(text))
+}
+
+/// Splits the text into messages of at most `max_length` UTF-16 code units.
+pub fn split_rich_text(text: &[RichText], max_length: usize) -> Vec<Vec<RichText>> {
+    let mut chunks = Vec::new();
+    let mut rest = Some(text.to_vec());
+    while let Some(text) = rest {
+        let (head, tail) = split_off_rich_text(&text, max_length);
+        chunks.push(head);
+        rest = tail;
+    }
+    chunks
+}
+
+/// Cuts the first message of at most `max_length` UTF-16 code units off the text.
+/// Prefers paragraph, line, sentence and word boundaries, in that order, in the second half of the message.
+/// Formatting at the cut continues in the rest, links and code are cut only if there is no other way.
+pub fn split_off_rich_text(text: &[RichText], max_length: usize) -> (Vec<RichText>, Option<Vec<RichText>>) {
+    if rich_text_length(text) <= max_length {
+        return (text.to_vec(), None)
+    }
+
+    let runs = runs(text);
+    let tokens = tokens(&runs);
+    let mut length = 0;
+    let mut breaks: [Option<Break>; 4] = [None; 4];
+    let mut hard_break = None;
+    for (index, token) in tokens.iter().enumerate() {
+        if index > 0 {
+            hard_break = Some(Break { start: index, resume: index, length });
+        }
+        if let Some((boundary, start)) = boundary(&runs, &tokens, index) {
+            breaks[boundary as usize] = Some(Break { start, resume: index + 1, length: length - (index - start) });
+        }
+
+        length += token.length;
+        if length > max_length && index > 0 {
+            break;
+        }
+    }
+
+    let cut = breaks.iter()
+    .flatten()
+    .find(|cut| cut.start > 0 && cut.length >= max_length / 2)
+    .or(hard_break.as_ref());
+    match cut {
+        Some(cut) if cut.resume < tokens.len() => (
+            from_runs(&slice(&runs, &tokens[..cut.start]), 0),
+            Some(from_runs(&slice(&runs, &tokens[cut.resume..]), 0))
+        ),
+        Some(cut) => (from_runs(&slice(&runs, &tokens[..cut.start]), 0), None),
+        None => (text.to_vec(), None)
+    }
+}
+
+/// Splits the text at empty lines. Formatting spanning several paragraphs continues in each of them.
+pub fn split_paragraphs(text: &[RichText]) -> Vec<Vec<RichText>> {
+    let runs = runs(text);
+    let tokens = tokens(&runs);
+    let mut paragraphs = vec![];
+    let mut start = 0;
+    for index in 0..tokens.len() {
+        if let Some((Boundary::Paragraph, end)) = boundary(&runs, &tokens, index) {
+            if end >= start {
+                paragraphs.push(from_runs(&slice(&runs, &tokens[start..end]), 0));
+            }
+            start = index + 1;
+        }
+    }
+    paragraphs.push(from_runs(&slice(&runs, &tokens[start.min(tokens.len())..]), 0));
+    paragraphs
+}
+
+/// Kinds of places where the text can be cut, from the most preferred one.
+#[derive(Clone, Copy)]
+enum Boundary {
+    Paragraph = 0,
+    Line = 1,
+    Sentence = 2,
+    Word = 3,
+}
+
+/// A place to cut the text: the head ends before `start` token and the rest begins with `resume` token.
+#[derive(Clone, Copy)]
+struct Break {
+    start: usize,
+    resume: usize,
+    length: usize,
+}
+
+#[derive(Clone, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+    Link(String),
+    Quote,
+    Spoiler,
+    Code,
+}
+
+/// A piece of plain text with the formatting of all nodes containing it.
+struct Run {
+    styles: Vec<Style>,
+    text: String,
+}
+
+/// An indivisible piece of a run, e.g. an emoji with its modifiers.
+struct Token {
+    run: usize,
+    range: Range<usize>,
+    length: usize,
+}
+
+impl Token {
+    fn text<'a>(&self, runs: &'a [Run]) -> &'a str {
+        &runs[self.run].text[self.range.clone()]
+    }
+}
+
+fn runs(text: &[RichText]) -> Vec<Run> {
+    let mut runs = vec![];
+    collect_runs(text, &mut vec![], &mut runs);
+    runs
+}
+
+fn collect_runs(text: &[RichText], styles: &mut Vec<Style>, runs: &mut Vec<Run>) {
+    for node in text {
+        let (style, children) = match node {
+            RichText::Plain(text) => {
+                runs.push(Run { styles: styles.clone(), text: text.clone() });
+                continue
+            },
+            RichText::Code(text) => {
+                let mut styles = styles.clone();
+                styles.push(Style::Code);
+                runs.push(Run { styles, text: text.clone() });
+                continue
+            },
+            RichText::Bold(children) => (Style::Bold, children),
+            RichText::Italic(children) => (Style::Italic, children),
+            RichText::Link(url, children) => (Style::Link(url.clone()), children),
+            RichText::Quote(children) => (Style::Quote, children),
+            RichText::Spoiler(children) => (Style::Spoiler, children),
+        };
+        styles.push(style);
+        collect_runs(children, styles, runs);
+        styles.pop();
+    }
+}
+
+/// Rebuilds the text from runs, merging adjacent runs with the same formatting into one node.
+fn from_runs(runs: &[Run], depth: usize) -> Vec<RichText> {
+    let mut text = vec![];
+    let mut index = 0;
+    while index < runs.len() {
+        let style = runs[index].styles.get(depth);
+        let count = runs[index..].iter().take_while(|run| run.styles.get(depth) == style).count();
+        let group = &runs[index..index + count];
+        match style {
+            None => text.extend(group.iter().map(|run| RichText::from(run.text.clone()))),
+            Some(Style::Code) => text.push(RichText::Code(group.iter().map(|run| run.text.as_str()).collect())),
+            Some(Style::Bold) => text.push(RichText::Bold(from_runs(group, depth + 1))),
+            Some(Style::Italic) => text.push(RichText::Italic(from_runs(group, depth + 1))),
+            Some(Style::Link(url)) => text.push(RichText::Link(url.clone(), from_runs(group, depth + 1))),
+            Some(Style::Quote) => text.push(RichText::Quote(from_runs(group, depth + 1))),
+            Some(Style::Spoiler) => text.push(RichText::Spoiler(from_runs(group, depth + 1))),
+        }
+        index += count;
+    }
+    text
+}
+
+/// Splits runs into tokens. A token starting a quoted line also counts the bar before it.
+fn tokens(runs: &[Run]) -> Vec<Token> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut line_start = true;
+    let mut quoted = false;
+    for (index, run) in runs.iter().enumerate() {
+        let run_quoted = run.styles.contains(&Style::Quote);
+        let mut offset = 0;
+        while offset < run.text.len() {
+            let end = offset + grapheme_length(&run.text[offset..]);
+            let text = &run.text[offset..end];
+            let bar_length = match run_quoted && (line_start || !quoted) {
+                true => 2,
+                false => 0
+            };
+            tokens.push(Token { run: index, range: offset..end, length: text_length(text) + bar_length });
+            line_start = text == "\n";
+            quoted = run_quoted;
+            offset = end;
+        }
+    }
+    tokens
+}
+
+fn slice(runs: &[Run], tokens: &[Token]) -> Vec<Run> {
+    let mut sliced: Vec<Run> = vec![];
+    let mut last_run = None;
+    for token in tokens {
+        match (sliced.last_mut(), last_run == Some(token.run)) {
+            (Some(run), true) => run.text.push_str(token.text(runs)),
+            _ => sliced.push(Run { styles: runs[token.run].styles.clone(), text: String::from(token.text(runs)) })
+        }
+        last_run = Some(token.run);
+    }
+    sliced
+}
+
+/// Returns the kind of the boundary at the whitespace token and the token the head should end before.
+fn boundary(runs: &[Run], tokens: &[Token], index: usize) -> Option<(Boundary, usize)> {
+    let styles = &runs[tokens[index].run].styles;
+    if styles.iter().any(|style| matches!(style, Style::Link(_) | Style::Code)) {
+        return None
+    }
+    let previous = index.checked_sub(1).map(|previous| tokens[previous].text(runs));
+    match (tokens[index].text(runs), previous) {
+        ("\n", Some("\n")) => Some((Boundary::Paragraph, index - 1)),
+        ("\n", _) => Some((Boundary::Line, index)),
+        (" ", Some(".") | Some("!") | Some("?") | Some("…")) => Some((Boundary::Sentence, index)),
+        (" ", _) => Some((Boundary::Word, index)),
+        _ => None
+    }
+}
+
+/// Keeps emoji with their modifiers and zero width joiner sequences together.
+fn grapheme_length(text: &str) -> usize {
+    let mut chars = text.chars().peekable();
+    let mut length = chars.next().map_or(0, char::len_utf8);
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\u{FE0F}' | '\u{20E3}' | '\u{1F3FB}'..='\u{1F3FF}' => {
+                length += c.len_utf8();
+                chars.next();
+            },
+            '\u{200D}' => {
+                length += c.len_utf8();
+                chars.next();
+                if let Some(joined) = chars.next() {
+                    length += joined.len_utf8();
+                }
+            },
+            _ => break
+        }
+    }
+    length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rich_text::{to_html, to_markdown_v2};
+
+    fn assert_chunks_fit(chunks: &[Vec<RichText>], max_length: usize) {
+        for chunk in chunks {
+            assert!(rich_text_length(chunk) <= max_length, "{} is longer than {}", to_plain_text(chunk), max_length);
+        }
+    }
+
+    fn plain(text: &str) -> Vec<RichText> {
+        vec![RichText::from(text)]
+    }
+
+    #[test]
+    fn keeps_short_text() {
+        let text = vec![RichText::from("Hello, "), RichText::Bold(vec![RichText::from("world")])];
+        assert_eq!(split_rich_text(&text, 4096), vec![text]);
+    }
+
+    #[test]
+    fn splits_at_paragraphs() {
+        let paragraph = "Lorem ipsum dolor sit amet. ".repeat(10);
+        let text = format!("{}\n\n{}\n\n{}", paragraph, paragraph, paragraph);
+        let chunks = split_rich_text(&plain(&text), 600);
+        assert_eq!(chunks, vec![plain(&format!("{}\n\n{}", paragraph, paragraph)), plain(&paragraph)]);
+    }
+
+    #[test]
+    fn splits_at_sentences() {
+        let text = "Rust 1.65 is out! ".repeat(50);
+        let chunks = split_rich_text(&plain(text.trim_end()), 100);
+        assert_chunks_fit(&chunks, 100);
+        for chunk in &chunks {
+            let text = to_plain_text(chunk);
+            assert!(text.starts_with("Rust 1.65"));
+            assert!(text.ends_with("out!"));
+        }
+    }
+
+    #[test]
+    fn measures_emoji_in_utf16_code_units() {
+        let text = "🦀 ".repeat(100);
+        let chunks = split_rich_text(&plain(text.trim_end()), 100);
+        assert_chunks_fit(&chunks, 100);
+        assert_eq!(chunks.iter().map(|chunk| to_plain_text(chunk)).collect::<String>().matches('🦀').count(), 100);
+        assert!(chunks.iter().all(|chunk| to_plain_text(chunk).ends_with('🦀')));
+    }
+
+    #[test]
+    fn keeps_emoji_sequences_together() {
+        let text = "👩‍💻👍🏽".repeat(30);
+        let chunks = split_rich_text(&plain(&text), 64);
+        assert_chunks_fit(&chunks, 64);
+        for chunk in &chunks {
+            let text = to_plain_text(chunk);
+            assert!(text.starts_with("👩‍💻"));
+            assert!(text.ends_with("👍🏽"));
+        }
+    }
+
+    #[test]
+    fn measures_text_without_escapes() {
+        // Every character is escaped in both parse modes, but only the displayed text counts.
+        let text = "<&>.-()".repeat(100);
+        let chunks = split_rich_text(&plain(&text), 350);
+        assert_eq!(chunks.len(), 2);
+        assert!(to_html(&chunks[0]).starts_with("&lt;&amp;&gt;"));
+        assert!(to_markdown_v2(&chunks[1]).starts_with("<&\\>\\.\\-\\(\\)"));
+    }
+
+    #[test]
+    fn never_cuts_links() {
+        let text = vec![
+            RichText::from("word".repeat(10)),
+            RichText::from(" "),
+            RichText::Link(String::from("https://doc.rust-lang.org"), vec![RichText::from("the docs")]),
+        ];
+        let chunks = split_rich_text(&text, 45);
+        assert_eq!(chunks, vec![plain(&"word".repeat(10)), vec![text[2].clone()]]);
+    }
+
+    #[test]
+    fn continues_formatting_in_next_message() {
+        let text = vec![RichText::Spoiler(vec![RichText::from("secret words. ".repeat(20).trim_end())])];
+        let chunks = split_rich_text(&text, 150);
+        assert_chunks_fit(&chunks, 150);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            let html = to_html(chunk);
+            assert!(html.starts_with("<tg-spoiler>secret"));
+            assert!(html.ends_with("words.</tg-spoiler>"));
+        }
+    }
+
+    #[test]
+    fn counts_quote_bars() {
+        let text = vec![RichText::from("Quote:\n"), RichText::Quote(vec![RichText::from("line\n".repeat(20).trim_end())])];
+        let chunks = split_rich_text(&text, 60);
+        assert_chunks_fit(&chunks, 60);
+        assert_eq!(to_plain_text(&chunks[1]), "┃ line\n".repeat(8).trim_end());
+    }
+
+    #[test]
+    fn splits_paragraphs_inside_formatting() {
+        let text = vec![RichText::from("Header\n\n"), RichText::Spoiler(vec![RichText::from("first\n\nsecond")])];
+        assert_eq!(split_paragraphs(&text), vec![
+            plain("Header"),
+            vec![RichText::Spoiler(vec![RichText::from("first")])],
+            vec![RichText::Spoiler(vec![RichText::from("second")])],
+        ]);
+    }
+}
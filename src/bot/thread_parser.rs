@@ -1,81 +1,152 @@
 use std::collections::HashMap;
 use std::string::String;
 
+use chrono::{DateTime, Utc};
 use egg_mode::raw::ParamList;
-use egg_mode::{raw, Response, Token};
-use egg_mode::tweet::{Tweet, show};
+use egg_mode::tweet::Tweet;
 use htmlescape::*;
 use reqwest::Url;
 use serde::{Serialize, Deserialize};
 
 use crate::bot_errors::{BotError, BotErrorKind};
-use crate::parser::{tweet_text_to_displayable_string, ParsedMedia, ImageEntity, VideoEntity, tweet_media};
+use crate::twitter_utils::TwitterApi;
+use crate::parser::{tweet_id_from_link, tweet_text_with_links, text_with_links, rich_text_with_links, ParsedMedia, ImageEntity, LinkCard, Poll, PollOption, TextLink, TweetMetrics, TweetTextEntity, VideoVariant, tweet_media, video_entity, apply_sensitive_media, apply_video_policy};
+use crate::chat_settings::SensitiveMedia;
+use crate::rich_text::RichText;
+use crate::video_policy::VideoPolicy;
 
 pub struct ThreadEntity {
+    pub id: u64,
+    pub created_at: Option<DateTime<Utc>>,
     pub text: String,
-    pub media_entities: Vec<ParsedMedia>
+    pub links: Vec<TextLink>,
+    pub media_entities: Vec<ParsedMedia>,
+    /// The tweet is marked as possibly sensitive.
+    pub sensitive: bool
 }
+
+impl ThreadEntity {
+    /// Returns the text with linked parts of it as links.
+    pub fn rich_text(&self) -> Vec<RichText> {
+        rich_text_with_links(self.text.as_str(), &self.links)
+    }
+}
+
 pub struct ThreadReply {
     pub user_name: Option<String>,
+    pub screen_name: Option<String>,
+    pub verified: bool,
+    /// Creation date of the first tweet in the thread.
+    pub created_at: Option<DateTime<Utc>>,
     pub thumb_url: Option<Url>,
-    pub entities: Vec<ThreadEntity>
-}
-
-pub async fn is_included_in_thread(tweet: &Tweet, token: &Token) -> Result<bool, BotError> {
-    if let Some(thread_user) = tweet.user.as_ref() {
-        if let Some(conversation_id) = conversation_id_from_tweet(tweet.id, &token).await? {
-            let replies = replies_in_conversation(
-                &conversation_id,
-                &thread_user.screen_name,
-                10, 
-                false,
-                None,
-                &token
-            ).await?;
-            return Ok(replies.data.len() >= 2);
+    pub entities: Vec<ThreadEntity>,
+    /// Set when the thread was walked backwards from the shared tweet, so its later tweets are unknown.
+    pub earlier_tweets_only: bool
+}
+
+impl ThreadReply {
+    pub fn apply_video_policy(&mut self, policy: &VideoPolicy) {
+        for entity in self.entities.iter_mut() {
+            apply_video_policy(&mut entity.media_entities, policy);
+        }
+    }
+
+    pub fn apply_sensitive_media(&mut self, mode: SensitiveMedia) {
+        for entity in self.entities.iter_mut().filter(|entity| entity.sensitive) {
+            apply_sensitive_media(&mut entity.media_entities, mode);
+        }
+    }
+
+    /// Drops tweets before the one with the ID, so the thread is unrolled from it onward.
+    pub fn start_from(&mut self, tweet_id: u64) -> Result<(), BotError> {
+        let index = match self.entities.iter().position(|entity| entity.id == tweet_id) {
+            Some(index) => index,
+            None => return Err(BotError::from(BotErrorKind::EmptyThread))
+        };
+        self.entities.drain(..index);
+        self.created_at = self.entities.first().and_then(|entity| entity.created_at);
+        Ok(())
+    }
+}
+
+/// Part of a thread to unroll when the link points to the middle of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThreadScope {
+    Whole,
+    FromTweet
+}
+
+impl ThreadScope {
+    /// Parses the tweet ID and the scope from `<id>` or `from_<id>`, which follow `unroll_` in callback data and deep links.
+    pub fn parse_target(target: &str) -> Result<(u64, ThreadScope), BotError> {
+        let (id, scope) = match target.strip_prefix("from_") {
+            Some(id) => (id, ThreadScope::FromTweet),
+            None => (target, ThreadScope::Whole)
+        };
+        let id = id.parse().map_err(|_| BotError::from(BotErrorKind::CallbackDataParsingError))?;
+        Ok((id, scope))
+    }
+}
+
+pub async fn is_included_in_thread(tweet_id: u64, api: &TwitterApi) -> Result<bool, BotError> {
+    let tweet = lookup_tweet(tweet_id, false, api).await?;
+    if let (Some(conversation_id), Some(thread_user)) = (&tweet.data.conversation_id, tweet.author()) {
+        let mut replies = replies_in_conversation(
+            conversation_id,
+            &thread_user.username,
+            10, 
+            false,
+            ConversationSearch::Recent,
+            None,
+            api
+        ).await?;
+        if replies.data.is_empty() && api.full_archive_search {
+            replies = replies_in_conversation(conversation_id, &thread_user.username, 10, false, ConversationSearch::FullArchive, None, api).await?;
+        }
+        // Old conversations aren't found without full-archive search, but a reply to the author's own tweet is still a part of a thread.
+        if replies.data.is_empty() {
+            return Ok(tweet.data.self_reply_parent_id().is_some());
         }
+        return Ok(replies.data.len() >= 2);
     }
 
     Ok(false)
 }
 
-pub async fn tweet_to_thread(start_tweet: &Tweet, token: &Token) -> Result<ThreadReply, BotError> {
+pub async fn tweet_to_thread(start_tweet: &Tweet, api: &TwitterApi) -> Result<ThreadReply, BotError> {
     if let Some(thread_user) = start_tweet.user.as_ref() {
         let start_id = start_tweet.id;
-        if let Some(conversation_id) = conversation_id_from_tweet(start_id, &token).await? {            
-            let response = all_replies_in_conversation(
-                &conversation_id, 
-                &thread_user.screen_name,
-                100,
-                true,
-                &token
-            ).await?;
-
-            let mut entities = vec![];
-            let first_reply_id = response.data.last().map(|t| { t.id.clone() }).unwrap();
-            let first_reply = show(first_reply_id.parse().unwrap(), &token).await?;
-            if let Some(head_id) = first_reply.in_reply_to_status_id {
-                let head = show(head_id, &token).await?;
-                entities.push(tweet_to_thread_entity(&head));
-            }
+        if let Some(conversation_id) = lookup_tweet(start_id, false, api).await?.data.conversation_id {            
+            let response = thread_replies(&conversation_id, &thread_user.screen_name, api).await?;
+
+            let earlier_tweets_only = response.data.is_empty();
+            let entities = match response.data.last() {
+                None => reply_chain(start_tweet, api).await?,
+                Some(first_reply) => {
+                    let first_reply_id = first_reply.id.parse().map_err(|_| BotError::from(BotErrorKind::InvalidThreadResponse))?;
+                    let first_reply = api.show(first_reply_id).await?;
+                    let mut entities = vec![];
+                    // The conversation may start with a tweet of another user, which isn't a part of the thread.
+                    if let Some(head_id) = self_reply_parent_id(&first_reply) {
+                        let head = api.show(head_id).await?;
+                        entities.push(thread_entity(&head, api).await?);
+                    }
 
-            let mut includes_map = HashMap::new();
-            if let Some(includes) = response.includes {
-                for media in includes.media {
-                    includes_map.insert(media.media_key.clone(), media.clone());
+                    entities.append(&mut conversation_to_thread_entities(&response));
+                    entities
                 }
-            }
-    
-            for reply in response.data.iter().rev() {
-                entities.push(conversation_reply_to_thread_entity(&reply, &includes_map));
-            }
+            };
     
             let name = decode_html(&thread_user.name)?;
             let thumb_url = Url::parse(thread_user.profile_image_url_https.as_str())?;
             return Ok(ThreadReply {
                 user_name: Some(name),
+                screen_name: Some(thread_user.screen_name.clone()),
+                verified: thread_user.verified,
+                created_at: entities.first().and_then(|head| head.created_at),
                 thumb_url: Some(thumb_url),
-                entities
+                entities,
+                earlier_tweets_only
             });
         } else {
             return Err(BotError::from(BotErrorKind::MissedConversationId))
@@ -85,46 +156,377 @@ pub async fn tweet_to_thread(start_tweet: &Tweet, token: &Token) -> Result<Threa
     }
 }
 
-async fn conversation_id_from_tweet(tweet_id: u64, token: &Token) -> Result<Option<String>, BotError> {
-    let url = format!("https://api.twitter.com/2/tweets/{}", tweet_id);
+/// Unrolls a thread using v2 API only. The head of the thread is the tweet with the conversation ID.
+pub async fn tweet_v2_to_thread(tweet_id: u64, api: &TwitterApi) -> Result<ThreadReply, BotError> {
+    let start_tweet = lookup_tweet(tweet_id, false, api).await?;
+    let thread_user = match start_tweet.author() {
+        Some(user) => user.clone(),
+        None => return Err(BotError::from(BotErrorKind::MissedUserInTweet))
+    };
+    let conversation_id = match &start_tweet.data.conversation_id {
+        Some(id) => id.clone(),
+        None => return Err(BotError::from(BotErrorKind::MissedConversationId))
+    };
+
+    let response = thread_replies(&conversation_id, &thread_user.username, api).await?;
+
+    let earlier_tweets_only = response.data.is_empty();
+    let entities = match earlier_tweets_only {
+        true => reply_chain_v2(tweet_id, api).await?,
+        false => {
+            let head_id = conversation_id.parse().map_err(|_| BotError::from(BotErrorKind::InvalidThreadResponse))?;
+            let head = lookup_tweet(head_id, true, api).await?;
+            let mut entities = vec![];
+            if head.data.author_id == start_tweet.data.author_id {
+                entities.push(conversation_reply_to_thread_entity(&head.data, &includes_media_map(&head.includes)));
+            }
+            entities.append(&mut conversation_to_thread_entities(&response));
+            entities
+        }
+    };
+
+    let name = decode_html(&thread_user.name)?;
+    let thumb_url = match &thread_user.profile_image_url {
+        Some(url) => Some(Url::parse(url.as_str())?),
+        None => None
+    };
+    Ok(ThreadReply {
+        user_name: Some(name),
+        screen_name: Some(thread_user.username.clone()),
+        verified: thread_user.verified.unwrap_or(false),
+        created_at: entities.first().and_then(|head| head.created_at),
+        thumb_url,
+        entities,
+        earlier_tweets_only
+    })
+}
+
+/// Maximum number of tweets loaded one by one when walking a reply chain.
+const MAX_REPLY_CHAIN_LENGTH: usize = 100;
+
+/// Walks the reply chain backwards from the last known tweet while the author replies to themselves.
+/// It's the fallback for threads the conversation search can't find. Returns entities from the oldest to the newest.
+async fn reply_chain(last_tweet: &Tweet, api: &TwitterApi) -> Result<Vec<ThreadEntity>, BotError> {
+    let mut entities = vec![thread_entity(last_tweet, api).await?];
+    let mut parent_id = self_reply_parent_id(last_tweet);
+    while let (Some(id), true) = (parent_id, entities.len() < MAX_REPLY_CHAIN_LENGTH) {
+        let tweet = api.show(id).await?;
+        entities.push(thread_entity(&tweet, api).await?);
+        parent_id = self_reply_parent_id(&tweet);
+    }
+    entities.reverse();
+    Ok(entities)
+}
+
+/// The same as `reply_chain` using v2 API only.
+async fn reply_chain_v2(last_tweet_id: u64, api: &TwitterApi) -> Result<Vec<ThreadEntity>, BotError> {
+    let mut entities = vec![];
+    let mut next_id = Some(last_tweet_id);
+    while let (Some(id), true) = (next_id, entities.len() < MAX_REPLY_CHAIN_LENGTH) {
+        let tweet = lookup_tweet(id, true, api).await?;
+        entities.push(conversation_reply_to_thread_entity(&tweet.data, &includes_media_map(&tweet.includes)));
+        next_id = tweet.data.self_reply_parent_id();
+    }
+    entities.reverse();
+    Ok(entities)
+}
+
+fn self_reply_parent_id(tweet: &Tweet) -> Option<u64> {
+    let author_id = tweet.user.as_ref()?.id;
+    match tweet.in_reply_to_user_id == Some(author_id) {
+        true => tweet.in_reply_to_status_id,
+        false => None
+    }
+}
+
+/// Looks up a single tweet with v2 API. The author is always expanded, media and polls only when `with_includes` is set.
+pub(crate) async fn lookup_tweet(tweet_id: u64, with_includes: bool, api: &TwitterApi) -> Result<TweetLookupResponse, BotError> {
+    let mut params = ParamList::new()
+        .add_param("tweet.fields", "conversation_id,author_id,in_reply_to_user_id,created_at,attachments,referenced_tweets,note_tweet,entities,public_metrics,possibly_sensitive")
+        .add_param("user.fields", "name,username,profile_image_url,verified");
+
+    if with_includes {
+        params = params
+        .add_param("expansions", "author_id,attachments.media_keys,attachments.poll_ids,referenced_tweets.id,referenced_tweets.id.author_id,referenced_tweets.id.attachments.media_keys")
+        .add_param("media.fields", "alt_text,duration_ms,height,media_key,preview_image_url,type,url,variants,width")
+        .add_param("poll.fields", "duration_minutes,end_datetime,id,options,voting_status");
+    } else {
+        params = params.add_param("expansions", "author_id");
+    }
+
+    api.get(format!("/2/tweets/{}", tweet_id).as_str(), &params).await
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TweetLookupResponse {
+    pub data: ConversationReply,
+    pub includes: Option<ConversationIncludes>,
+}
+
+impl TweetLookupResponse {
+    pub fn author(&self) -> Option<&ConversationIncludesUser> {
+        self.includes.as_ref()?.author(&self.data)
+    }
 
-    let params = raw::ParamList::new()
-        .add_param("tweet.fields", "conversation_id");
+    pub fn poll(&self, tweet: &ConversationReply) -> Option<Poll> {
+        let poll_id = tweet.attachments.as_ref()?.poll_ids.first()?;
+        let poll = self.includes.as_ref()?.polls.iter().find(|poll| &poll.id == poll_id)?;
+        let mut options = poll.options.clone();
+        options.sort_by_key(|option| option.position);
+        Some(Poll {
+            options: options.into_iter().map(|option| PollOption { label: option.label, votes: option.votes }).collect(),
+            open: poll.voting_status.as_deref() == Some("open"),
+            end_datetime: poll.end_datetime
+        })
+    }
 
-    let req = raw::request_get(url.as_str(), &token, Some(&params));
-    let output: Response<serde_json::Value> = raw::response_json(req).await?;
-    match output.response["data"]["conversation_id"].as_str() {
-        Some(str) => Ok(Some(String::from(str))),
-        _ => Ok(None)
+    /// Returns the quoted or retweeted tweet if it's expanded. `kind` is the reference type, e.g. "quoted".
+    pub fn referenced_tweet(&self, kind: &str) -> Option<&ConversationReply> {
+        let referenced_id = &self.data.referenced_tweets.iter()
+        .find(|referenced| referenced.r#type == kind)?
+        .id;
+        self.includes.as_ref()?.tweets.iter().find(|tweet| &tweet.id == referenced_id)
     }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationResponse {
+pub(crate) struct ConversationResponse {
+    #[serde(default)]
     pub data: Vec<ConversationReply>,
     pub includes: Option<ConversationIncludes>,
+    #[serde(default)]
     pub meta: ConversationMeta,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationReply {
+pub(crate) struct ConversationReply {
     pub id: String,
     pub text: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub author_id: Option<String>,
+    pub in_reply_to_user_id: Option<String>,
+    pub conversation_id: Option<String>,
     pub attachments: Option<ConversationReplyAttachments>,
+    #[serde(default)]
+    pub referenced_tweets: Vec<ConversationReferencedTweet>,
+    pub note_tweet: Option<ConversationNoteTweet>,
+    pub entities: Option<ConversationEntities>,
+    pub public_metrics: Option<ConversationPublicMetrics>,
+    pub possibly_sensitive: Option<bool>,
+}
+
+impl ConversationReply {
+    /// Returns the displayable text with expanded links, mentions and hashtags.
+    /// The untruncated text of a long (note) tweet is preferred.
+    pub fn text_with_links(&self) -> (String, Vec<TextLink>) {
+        let (text, entities) = match &self.note_tweet {
+            Some(note_tweet) => (&note_tweet.text, &note_tweet.entities),
+            None => (&self.text, &self.entities)
+        };
+        let entities = match entities {
+            Some(entities) => entities.text_entities(&self.hidden_tweet_ids()),
+            None => vec![]
+        };
+        text_with_links(text, entities)
+    }
+
+    pub fn metrics(&self) -> Option<TweetMetrics> {
+        self.public_metrics.as_ref().map(|metrics| TweetMetrics {
+            likes: metrics.like_count,
+            retweets: metrics.retweet_count,
+            replies: Some(metrics.reply_count),
+            views: metrics.impression_count
+        })
+    }
+
+    /// Returns a card of the last shared link unless the tweet has media.
+    pub fn link_card(&self) -> Option<LinkCard> {
+        if self.attachments.as_ref().map(|attachments| !attachments.media_keys.is_empty()).unwrap_or(false) {
+            return None;
+        }
+        self.entities.as_ref()?.urls.iter()
+        .filter(|url| url.media_key.is_none())
+        .rev()
+        .find(|url| tweet_id_from_link(url.expanded_url.as_ref().unwrap_or(&url.url)).is_err())
+        .and_then(|url| url.link_card())
+    }
+
+    /// Returns ID of the replied tweet if the author replies to themselves, i.e. continues a thread.
+    pub fn self_reply_parent_id(&self) -> Option<u64> {
+        if self.in_reply_to_user_id.is_none() || self.in_reply_to_user_id != self.author_id {
+            return None;
+        }
+        self.referenced_tweets.iter()
+        .find(|referenced| referenced.r#type == "replied_to")
+        .and_then(|referenced| referenced.id.parse().ok())
+    }
+
+    /// Returns IDs of tweets which links aren't displayed: the tweet itself and the quoted one.
+    fn hidden_tweet_ids(&self) -> Vec<u64> {
+        self.referenced_tweets.iter()
+        .filter(|referenced| referenced.r#type == "quoted")
+        .map(|referenced| referenced.id.as_str())
+        .chain(std::iter::once(self.id.as_str()))
+        .filter_map(|id| id.parse().ok())
+        .collect::<Vec<_>>()
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationReplyAttachments {
-    pub media_keys: Vec<String>
+pub(crate) struct ConversationPublicMetrics {
+    pub retweet_count: i64,
+    pub reply_count: i64,
+    pub like_count: i64,
+    pub impression_count: Option<i64>
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationIncludes {
-    pub media: Vec<ConversationIncludesMediaEntity>
+pub(crate) struct ConversationNoteTweet {
+    pub text: String,
+    pub entities: Option<ConversationEntities>
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationIncludesMediaEntity {
+pub(crate) struct ConversationEntities {
+    #[serde(default)]
+    pub urls: Vec<ConversationUrlEntity>,
+    #[serde(default)]
+    pub mentions: Vec<ConversationMentionEntity>,
+    #[serde(default)]
+    pub hashtags: Vec<ConversationHashtagEntity>
+}
+
+impl ConversationEntities {
+    fn text_entities(&self, hidden_tweet_ids: &[u64]) -> Vec<TweetTextEntity> {
+        let mut entities = vec![];
+        for url in &self.urls {
+            let expanded_url = url.expanded_url.clone().unwrap_or(url.url.clone());
+            let linked_id = tweet_id_from_link(&expanded_url).ok();
+            let hidden = url.media_key.is_some() || linked_id.map(|id| hidden_tweet_ids.contains(&id)).unwrap_or(false);
+            match (hidden, &url.display_url) {
+                (false, Some(display_url)) => entities.push(TweetTextEntity::Url { start: url.start, url: url.url.clone(), display_url: display_url.clone(), expanded_url }),
+                _ => entities.push(TweetTextEntity::Hidden { start: url.start, url: url.url.clone() })
+            }
+        }
+        for mention in &self.mentions {
+            entities.push(TweetTextEntity::Mention { start: mention.start, screen_name: mention.username.clone() });
+        }
+        for hashtag in &self.hashtags {
+            entities.push(TweetTextEntity::Hashtag { start: hashtag.start, tag: hashtag.tag.clone() });
+        }
+        entities
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationUrlEntity {
+    pub start: usize,
+    pub url: String,
+    pub expanded_url: Option<String>,
+    pub display_url: Option<String>,
+    pub media_key: Option<String>,
+    pub unwound_url: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub images: Vec<ConversationUrlImage>
+}
+
+impl ConversationUrlEntity {
+    fn link_card(&self) -> Option<LinkCard> {
+        let url = self.unwound_url.as_ref().or(self.expanded_url.as_ref()).unwrap_or(&self.url);
+        let image = self.images.first().and_then(|image| {
+            let url = Url::parse(image.url.as_str()).ok()?;
+            Some(ParsedMedia::Image(ImageEntity { id: image.url.clone(), url, width: image.width, height: image.height, alt_text: None, has_spoiler: false }))
+        });
+        Some(LinkCard {
+            url: Url::parse(url.as_str()).ok()?,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            image
+        })
+    }
+}
+
+/// Card images from the largest to the smallest.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationUrlImage {
+    pub url: String,
+    pub width: i32,
+    pub height: i32
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationMentionEntity {
+    pub start: usize,
+    pub username: String
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationHashtagEntity {
+    pub start: usize,
+    pub tag: String
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationReferencedTweet {
+    pub r#type: String,
+    pub id: String
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationReplyAttachments {
+    #[serde(default)]
+    pub media_keys: Vec<String>,
+    #[serde(default)]
+    pub poll_ids: Vec<String>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationIncludes {
+    #[serde(default)]
+    pub media: Vec<ConversationIncludesMediaEntity>,
+    #[serde(default)]
+    pub users: Vec<ConversationIncludesUser>,
+    #[serde(default)]
+    pub tweets: Vec<ConversationReply>,
+    #[serde(default)]
+    pub polls: Vec<ConversationPoll>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationPoll {
+    pub id: String,
+    pub options: Vec<ConversationPollOption>,
+    pub voting_status: Option<String>,
+    pub end_datetime: Option<DateTime<Utc>>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationPollOption {
+    pub position: i32,
+    pub label: String,
+    pub votes: i64
+}
+
+impl ConversationIncludes {
+    pub fn author(&self, tweet: &ConversationReply) -> Option<&ConversationIncludesUser> {
+        let author_id = tweet.author_id.as_ref()?;
+        self.users.iter().find(|user| &user.id == author_id)
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationIncludesUser {
+    pub id: String,
+    pub name: String,
+    pub username: String,
+    pub profile_image_url: Option<String>,
+    pub verified: Option<bool>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ConversationIncludesMediaEntity {
     pub media_key: String,
     pub r#type: String,
     pub width: i32,
@@ -132,29 +534,61 @@ struct ConversationIncludesMediaEntity {
     pub url: Option<String>,
     pub preview_image_url: Option<String>,
     pub duration_ms: Option<i32>,
-    pub variants: Option<Vec<ConversationIncludesMediaVariant>>
+    pub variants: Option<Vec<ConversationIncludesMediaVariant>>,
+    pub alt_text: Option<String>
 }                 
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationIncludesMediaVariant {
+pub(crate) struct ConversationIncludesMediaVariant {
     pub content_type: String,
     pub url: String,
     pub bit_rate: Option<i32>
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-struct ConversationMeta {
+pub(crate) struct ConversationMeta {
     pub next_token: Option<String>,
 }
 
-async fn all_replies_in_conversation(conversation_id: &String, user_screen_name: &String, max_count: u64, with_includes: bool, token: &Token) -> Result<ConversationResponse, BotError> {
-    let result: ConversationResponse = replies_in_conversation(conversation_id, user_screen_name, max_count, with_includes, None, token).await?;
+/// Endpoint to search replies in a conversation with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ConversationSearch {
+    /// Covers the last seven days only.
+    Recent,
+    /// Covers the whole history, but needs elevated API access. See `TwitterApi::full_archive_search`.
+    FullArchive
+}
+
+impl ConversationSearch {
+    fn path(&self) -> &'static str {
+        match self {
+            ConversationSearch::Recent => "/2/tweets/search/recent",
+            ConversationSearch::FullArchive => "/2/tweets/search/all"
+        }
+    }
+}
+
+/// Creation time of the first tweet ever, the earliest start time of a search.
+const FIRST_TWEET_TIME: &str = "2006-03-21T00:00:00Z";
+
+/// Returns replies of the thread author from the newest to the oldest. Conversations older than a week are searched
+/// in the full archive when it's available, otherwise the response is empty.
+async fn thread_replies(conversation_id: &String, user_screen_name: &String, api: &TwitterApi) -> Result<ConversationResponse, BotError> {
+    let response = all_replies_in_conversation(conversation_id, user_screen_name, 100, true, ConversationSearch::Recent, api).await?;
+    if response.data.is_empty() && api.full_archive_search {
+        return all_replies_in_conversation(conversation_id, user_screen_name, 100, true, ConversationSearch::FullArchive, api).await;
+    }
+    Ok(response)
+}
+
+async fn all_replies_in_conversation(conversation_id: &String, user_screen_name: &String, max_count: u64, with_includes: bool, search: ConversationSearch, api: &TwitterApi) -> Result<ConversationResponse, BotError> {
+    let result: ConversationResponse = replies_in_conversation(conversation_id, user_screen_name, max_count, with_includes, search, None, api).await?;
     let mut replies = result.data;
     let mut includes = result.includes.map(|i| i.media).unwrap_or(vec![]);
     let mut next_token = result.meta.next_token;
 
     while let Some(next_token_value) = &next_token {
-        let next_result = replies_in_conversation(conversation_id, user_screen_name, max_count, with_includes, Some(next_token_value), token).await?;
+        let next_result = replies_in_conversation(conversation_id, user_screen_name, max_count, with_includes, search, Some(next_token_value), api).await?;
         let mut next_replies = next_result.data;
         replies.append(&mut next_replies);
 
@@ -168,17 +602,17 @@ async fn all_replies_in_conversation(conversation_id: &String, user_screen_name:
         data: replies,
         includes: match includes.len() {
             0 => None,
-            _ => Some(ConversationIncludes { media: includes })
+            _ => Some(ConversationIncludes { media: includes, ..Default::default() })
         },
         meta: ConversationMeta { next_token: None },
     })
 }
 
-async fn replies_in_conversation(conversation_id: &String, user_screen_name: &String, max_count: u64, with_includes: bool, next_token: Option<&String>, token: &Token) -> Result<ConversationResponse, BotError> {
-    let url = format!("https://api.twitter.com/2/tweets/search/recent");
+pub(crate) async fn replies_in_conversation(conversation_id: &String, user_screen_name: &String, max_count: u64, with_includes: bool, search: ConversationSearch, next_token: Option<&String>, api: &TwitterApi) -> Result<ConversationResponse, BotError> {
     let mut params = ParamList::new()
     .add_param("query", format!("conversation_id:{} from:{} to:{}", conversation_id, user_screen_name, user_screen_name))
-    .add_param("max_results", max_count.to_string());
+    .add_param("max_results", max_count.to_string())
+    .add_param("tweet.fields", "created_at,attachments,note_tweet,entities,possibly_sensitive");
 
     if with_includes {
         params = params
@@ -186,30 +620,96 @@ async fn replies_in_conversation(conversation_id: &String, user_screen_name: &St
         .add_param("media.fields", "alt_text,duration_ms,height,media_key,preview_image_url,type,url,variants,width");
     }
 
+    // Full-archive search only covers the last 30 days unless the start time is set.
+    if search == ConversationSearch::FullArchive {
+        params = params.add_param("start_time", FIRST_TWEET_TIME);
+    }
+
     if let Some(next) = next_token {
         params = params.add_param("next_token", next.clone())
     }
 
-    let req = raw::request_get(url.as_str(), &token, Some(&params));
-    let output: Response<ConversationResponse> = raw::response_json(req).await?;
-    Ok(output.response)
+    api.get(search.path(), &params).await
 }
 
-fn tweet_to_thread_entity(tweet: &Tweet) -> ThreadEntity {
+/// Returns thread entities for conversation replies from the oldest to the newest.
+fn conversation_to_thread_entities(response: &ConversationResponse) -> Vec<ThreadEntity> {
+    let includes_map = includes_media_map(&response.includes);
+    response.data.iter().rev()
+    .map(|reply| conversation_reply_to_thread_entity(reply, &includes_map))
+    .collect::<Vec<_>>()
+}
+
+pub(crate) fn includes_media_map(includes: &Option<ConversationIncludes>) -> HashMap<String, ConversationIncludesMediaEntity> {
+    let mut includes_map = HashMap::new();
+    if let Some(includes) = includes {
+        for media in &includes.media {
+            includes_map.insert(media.media_key.clone(), media.clone());
+        }
+    }
+    includes_map
+}
+
+/// Returns the poll and the link card of the tweet. v1.1 API doesn't expose them, so they're loaded with v2 API.
+pub(crate) async fn tweet_attachments(tweet_id: u64, api: &TwitterApi) -> Result<(Option<Poll>, Option<LinkCard>), BotError> {
+    let response = lookup_tweet(tweet_id, true, api).await?;
+    Ok((response.poll(&response.data), response.data.link_card()))
+}
+
+/// Returns the displayable full text of a long (note) tweet, which v1.1 API cuts with a link to the tweet.
+pub(crate) async fn note_tweet_text(tweet: &Tweet, api: &TwitterApi) -> Result<Option<(String, Vec<TextLink>)>, BotError> {
+    if !is_note_tweet(tweet) {
+        return Ok(None)
+    }
+    let response = lookup_tweet(tweet.id, false, api).await?;
+    match response.data.note_tweet {
+        Some(_) => Ok(Some(response.data.text_with_links())),
+        None => Ok(None)
+    }
+}
+
+fn is_note_tweet(tweet: &Tweet) -> bool {
+    let link = format!("/i/web/status/{}", tweet.id);
+    tweet.truncated || tweet.entities.urls.iter().any(|url| {
+        url.expanded_url.as_ref().map(|expanded_url| expanded_url.ends_with(link.as_str())).unwrap_or(false)
+    })
+}
+
+/// Returns the thread entity with the full text if it's a long (note) tweet.
+async fn thread_entity(tweet: &Tweet, api: &TwitterApi) -> Result<ThreadEntity, BotError> {
+    let mut entity = tweet_to_thread_entity(tweet);
+    if let Some((text, links)) = note_tweet_text(tweet, api).await? {
+        entity.text = text;
+        entity.links = links;
+    }
+    Ok(entity)
+}
+
+pub(crate) fn tweet_to_thread_entity(tweet: &Tweet) -> ThreadEntity {
+    let (text, links) = tweet_text_with_links(tweet);
     ThreadEntity {
-        text: tweet_text_to_displayable_string(&tweet.text),
+        id: tweet.id,
+        created_at: Some(tweet.created_at),
+        text,
+        links,
         media_entities: tweet_media(&tweet),
+        sensitive: tweet.possibly_sensitive.unwrap_or(false),
     }
 }
 
 fn conversation_reply_to_thread_entity(reply: &ConversationReply, includes: &HashMap<String, ConversationIncludesMediaEntity>) -> ThreadEntity {
+    let (text, links) = reply.text_with_links();
     ThreadEntity {
-        text: tweet_text_to_displayable_string(&reply.text),
-        media_entities: conversation_reply_media(reply, includes),    
+        id: reply.id.parse().unwrap_or_default(),
+        created_at: reply.created_at,
+        text,
+        links,
+        media_entities: conversation_reply_media(reply, includes),
+        sensitive: reply.possibly_sensitive.unwrap_or(false),
     }
 }
 
-fn conversation_reply_media(reply: &ConversationReply, includes: &HashMap<String, ConversationIncludesMediaEntity>) -> Vec<ParsedMedia> {
+pub(crate) fn conversation_reply_media(reply: &ConversationReply, includes: &HashMap<String, ConversationIncludesMediaEntity>) -> Vec<ParsedMedia> {
     let mut media_entities: Vec<ConversationIncludesMediaEntity> = vec![];
     if let Some(attachments) = &reply.attachments {
         for media_key in &attachments.media_keys {
@@ -231,19 +731,15 @@ fn conversation_reply_media(reply: &ConversationReply, includes: &HashMap<String
         if let Ok(thumb_url) = Url::parse(preview_url.as_str()) {
             let id = entity.media_key.clone();
             if let Some(variants) = &entity.variants {
-                let mut mp4_variants = variants.iter().filter(|v| v.content_type == "video/mp4").collect::<Vec<_>>();
-                mp4_variants.sort_by_key(|v| v.bit_rate.unwrap_or(0));
-                if let Some(variant) = mp4_variants.last() {
-                    if let Ok(url) = Url::parse(variant.url.as_str()) {
-                        return Some(ParsedMedia::Video(VideoEntity {
-                            id,
-                            url: url,
-                            thumb_url: thumb_url,
-                            mime_type: "video/mp4".parse().unwrap(),
-                            width: entity.width,
-                            height: entity.height
-                        }));
-                    }
+                let variants = variants.iter()
+                .filter(|v| v.content_type == "video/mp4")
+                .filter_map(|v| Url::parse(v.url.as_str()).ok().map(|url| VideoVariant { url, bitrate: v.bit_rate }))
+                .collect::<Vec<_>>();
+                if let Some(video) = video_entity(id.clone(), thumb_url.clone(), entity.width, entity.height, entity.duration_ms, variants) {
+                    return match entity.r#type.as_str() {
+                        "animated_gif" => Some(ParsedMedia::Animation(video)),
+                        _ => Some(ParsedMedia::Video(video))
+                    };
                 }
             }
 
@@ -251,7 +747,9 @@ fn conversation_reply_media(reply: &ConversationReply, includes: &HashMap<String
                 id,
                 url: thumb_url,
                 width: entity.width,
-                height: entity.height
+                height: entity.height,
+                alt_text: entity.alt_text.clone(),
+                has_spoiler: false
             }));
         }
 
@@ -0,0 +1,36 @@
+use std::env;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::bot_errors::BotError;
+use crate::parser::Reply;
+use crate::thread_parser::ThreadReply;
+use crate::twitter_utils::TwitterApi;
+use crate::twitter_v1_source::TwitterV1Source;
+use crate::twitter_v2_source::TwitterV2Source;
+use crate::fixture_tweet_source::FixtureTweetSource;
+
+/// A backend which loads tweets and converts them to replies.
+#[async_trait]
+pub trait TweetSource: Sync + Send {
+    async fn reply(&self, id: u64) -> Result<Reply, BotError>;
+
+    async fn thread(&self, id: u64) -> Result<ThreadReply, BotError>;
+
+    async fn is_included_in_thread(&self, id: u64) -> Result<bool, BotError>;
+}
+
+/// Returns a tweet source selected with `TWEET_SOURCE`: `v1` (default), `v2` or `fixtures`.
+/// Fixtures are loaded from the directory set in `TWEET_FIXTURES_PATH`. The source is created once at startup.
+pub async fn tweet_source_from_env() -> Result<Arc<dyn TweetSource>, BotError> {
+    let source = env::var("TWEET_SOURCE").unwrap_or(String::from("v1"));
+    match source.to_lowercase().as_str() {
+        "v2" => Ok(Arc::new(TwitterV2Source { api: TwitterApi::from_env().await? })),
+        "fixtures" => {
+            let path = env::var("TWEET_FIXTURES_PATH").expect("TWEET_FIXTURES_PATH not set");
+            Ok(Arc::new(FixtureTweetSource::from_dir(path)?))
+        },
+        _ => Ok(Arc::new(TwitterV1Source { api: TwitterApi::from_env().await? }))
+    }
+}
@@ -1,7 +1,71 @@
+use std::env;
+
 use egg_mode::*;
+use egg_mode::raw::ParamList;
+use egg_mode::tweet::Tweet;
+use serde::de::DeserializeOwned;
+
 use crate::bot_errors::BotError;
+use crate::chat_settings::env_switch;
+
+const DEFAULT_TWITTER_API_URL: &str = "https://api.twitter.com";
+
+/// Twitter API credentials and base URL. The URL can be pointed to a local server with `TWITTER_API_URL`.
+#[derive(Clone, Debug)]
+pub struct TwitterApi {
+    pub token: Token,
+    pub base_url: String,
+    /// Full-archive search needs elevated API access. Enabled with `TWITTER_FULL_ARCHIVE_SEARCH`.
+    pub full_archive_search: bool,
+}
+
+impl TwitterApi {
+    pub async fn from_env() -> Result<TwitterApi, BotError> {
+        Ok(TwitterApi {
+            token: twitter_token().await?,
+            base_url: env::var("TWITTER_API_URL").unwrap_or(String::from(DEFAULT_TWITTER_API_URL)),
+            full_archive_search: env_switch("TWITTER_FULL_ARCHIVE_SEARCH"),
+        })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url.trim_end_matches('/'), path)
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str, params: &ParamList) -> Result<T, BotError> {
+        let req = raw::request_get(self.url(path).as_str(), &self.token, Some(params));
+        let output: Response<T> = raw::response_json(req).await?;
+        Ok(output.response)
+    }
+
+    /// Looks up a single tweet with v1.1 API. Mirrors `egg_mode::tweet::show`.
+    pub async fn show(&self, id: u64) -> Result<Tweet, BotError> {
+        let params = ParamList::new()
+            .add_param("id", id.to_string())
+            .add_param("tweet_mode", "extended")
+            .add_param("include_my_retweet", "true")
+            .add_param("include_ext_alt_text", "true");
+        self.get("/1.1/statuses/show.json", &params).await
+    }
+}
 
 pub async fn twitter_api_token_value(client_id: String, secret: String) -> Result<Token, BotError> {
     let con_token = KeyPair::new(client_id, secret);
     Ok(auth::bearer_token(&con_token).await?)
+}
+
+/// Returns a bearer token from `TWITTER_BEARER_TOKEN` or requests a new one with the client credentials.
+pub async fn twitter_token() -> Result<Token, BotError> {
+    if let Ok(token_value) = env::var("TWITTER_BEARER_TOKEN") {
+        let token = Token::Bearer(token_value);
+        return Ok(token);
+    }
+
+    let twitter_client_id = env::var("TWITTER_CLIENT_ID").expect("TWITTER_CLIENT_ID not set");
+    let twitter_secret = env::var("TWITTER_SECRET").expect("TWITTER_SECRET not set");
+    let token = twitter_api_token_value(twitter_client_id, twitter_secret).await?;
+    if let Token::Bearer(token_value) = &token {
+        env::set_var("TWITTER_BEARER_TOKEN", token_value);
+    }
+    Ok(token)
 }
\ No newline at end of file
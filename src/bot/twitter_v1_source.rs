@@ -0,0 +1,52 @@
+use async_trait::async_trait;
+
+use crate::bot_errors::BotError;
+use crate::parser::{tweet_to_reply, Reply};
+use crate::thread_parser::{is_included_in_thread, note_tweet_text, tweet_attachments, tweet_to_thread, ThreadReply};
+use crate::tweet_source::TweetSource;
+use crate::twitter_utils::TwitterApi;
+
+/// Loads tweets with v1.1 API. Threads are still searched with v2 API because v1.1 has no conversation search.
+pub struct TwitterV1Source {
+    pub api: TwitterApi
+}
+
+#[async_trait]
+impl TweetSource for TwitterV1Source {
+    async fn reply(&self, id: u64) -> Result<Reply, BotError> {
+        let tweet = self.api.show(id).await?;
+        let mut reply = tweet_to_reply(&tweet).await?;
+        let original_tweet = tweet.retweeted_status.as_deref().unwrap_or(&tweet);
+        // The truncated text is kept if the full one can't be loaded with v2 API.
+        match note_tweet_text(original_tweet, &self.api).await {
+            Ok(Some((text, links))) => {
+                reply.text = text;
+                reply.links = links;
+            },
+            Ok(None) => {},
+            Err(error) => log::error!("Failed to load the full text of tweet {}: {}", original_tweet.id, error)
+        }
+        // Polls and link cards aren't shown with media, so only tweets without media are checked.
+        // They're optional, so the tweet is still converted if v2 API isn't available.
+        if original_tweet.extended_entities.is_none() {
+            let (poll, card) = tweet_attachments(original_tweet.id, &self.api).await.unwrap_or_else(|error| {
+                log::error!("Failed to load attachments of tweet {}: {}", original_tweet.id, error);
+                (None, None)
+            });
+            reply.poll = poll;
+            if card.is_some() {
+                reply.card = card;
+            }
+        }
+        Ok(reply)
+    }
+
+    async fn thread(&self, id: u64) -> Result<ThreadReply, BotError> {
+        let tweet = self.api.show(id).await?;
+        tweet_to_thread(&tweet, &self.api).await
+    }
+
+    async fn is_included_in_thread(&self, id: u64) -> Result<bool, BotError> {
+        is_included_in_thread(id, &self.api).await
+    }
+}
@@ -0,0 +1,96 @@
+use async_trait::async_trait;
+use htmlescape::*;
+use reqwest::Url;
+
+use crate::bot_errors::{BotError, BotErrorKind};
+use crate::parser::Reply;
+use crate::thread_parser::{conversation_reply_media, includes_media_map, is_included_in_thread, lookup_tweet, tweet_v2_to_thread, ConversationIncludesUser, ConversationReply, ThreadReply, TweetLookupResponse};
+use crate::tweet_source::TweetSource;
+use crate::twitter_utils::TwitterApi;
+
+/// Loads tweets with v2 API only.
+pub struct TwitterV2Source {
+    pub api: TwitterApi
+}
+
+#[async_trait]
+impl TweetSource for TwitterV2Source {
+    async fn reply(&self, id: u64) -> Result<Reply, BotError> {
+        let response = lookup_tweet(id, true, &self.api).await?;
+        lookup_response_to_reply(&response)
+    }
+
+    async fn thread(&self, id: u64) -> Result<ThreadReply, BotError> {
+        tweet_v2_to_thread(id, &self.api).await
+    }
+
+    async fn is_included_in_thread(&self, id: u64) -> Result<bool, BotError> {
+        is_included_in_thread(id, &self.api).await
+    }
+}
+
+fn lookup_response_to_reply(response: &TweetLookupResponse) -> Result<Reply, BotError> {
+    if let Some(original_tweet) = response.referenced_tweet("retweeted") {
+        let original_author = response.includes.as_ref().and_then(|includes| includes.author(original_tweet));
+        let mut reply = conversation_reply_to_reply(original_tweet, original_author, response)?;
+        reply.retweeted_by = response.author().map(|user| decode_html(&user.name)).transpose()?;
+        return Ok(reply);
+    }
+
+    let mut reply = conversation_reply_to_reply(&response.data, response.author(), response)?;
+    if let Some(quoted_tweet) = response.referenced_tweet("quoted") {
+        let quoted_author = response.includes.as_ref().and_then(|includes| includes.author(quoted_tweet));
+        reply.quoted = Some(Box::new(conversation_reply_to_reply(quoted_tweet, quoted_author, response)?));
+    }
+    Ok(reply)
+}
+
+fn conversation_reply_to_reply(tweet: &ConversationReply, author: Option<&ConversationIncludesUser>, response: &TweetLookupResponse) -> Result<Reply, BotError> {
+    let (text, links) = tweet.text_with_links();
+    let id = tweet.id.parse().map_err(|_| BotError::from(BotErrorKind::TweetParsingError))?;
+    let media_entities = conversation_reply_media(tweet, &includes_media_map(&response.includes));
+    if let Some(user) = author {
+        let name = decode_html(&user.name)?;
+        let thumb_url = match &user.profile_image_url {
+            Some(url) => Some(Url::parse(url.as_str())?),
+            None => None
+        };
+        return Ok(Reply {
+            id,
+            user_name: Some(name),
+            screen_name: Some(user.username.clone()),
+            verified: user.verified.unwrap_or(false),
+            created_at: tweet.created_at,
+            thumb_url,
+            text,
+            links,
+            media_entities,
+            quoted: None,
+            retweeted_by: None,
+            metrics: tweet.metrics(),
+            poll: response.poll(tweet),
+            card: tweet.link_card(),
+            sensitive: tweet.possibly_sensitive.unwrap_or(false),
+            continues_thread: tweet.self_reply_parent_id().is_some(),
+        });
+    } else {
+        return Ok(Reply {
+            id,
+            user_name: None,
+            screen_name: None,
+            verified: false,
+            created_at: tweet.created_at,
+            thumb_url: None,
+            text,
+            links,
+            media_entities,
+            quoted: None,
+            retweeted_by: None,
+            metrics: tweet.metrics(),
+            poll: response.poll(tweet),
+            card: tweet.link_card(),
+            sensitive: tweet.possibly_sensitive.unwrap_or(false),
+            continues_thread: tweet.self_reply_parent_id().is_some(),
+        });
+    }
+}
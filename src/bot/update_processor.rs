@@ -1,17 +1,24 @@
 use std::convert::TryInto;
 use std::string::String;
 use async_trait::async_trait;
-use egg_mode::*;
-use teloxide::payloads::{SendVideoSetters, SendPhotoSetters, SendMessageSetters};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use teloxide::payloads::{SendPhotoSetters, SendMessageSetters};
 use teloxide::requests::Requester;
-use teloxide::types::{ParseMode, ChatId, InputFile, InputMedia, InputMediaPhoto, InputMediaVideo};
+use teloxide::types::{ParseMode, ChatId, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaDocument, InputMediaPhoto, InputMediaVideo};
 
 use crate::analytics::track_hit;
-use crate::thread_parser::{tweet_to_thread, ThreadReply, ThreadEntity};
-use crate::{bot_errors::BotError, thread_parser::is_included_in_thread};
+use crate::bot_errors::BotError;
+use crate::chat_settings::{ChatSettings, ChatSettingsStore, SensitiveMedia};
+use crate::rich_text::{join, render, RichText};
+use crate::text_splitter::{rich_text_length, split_off_rich_text, split_paragraphs, split_rich_text};
+use crate::thread_parser::{ThreadReply, ThreadEntity, ThreadScope};
+use crate::tweet_source::TweetSource;
+use crate::video_uploader::{download_file, send_animation, send_video, MAX_UPLOAD_SIZE};
+use crate::video_policy::VideoPolicy;
 use crate::parser::*;
 
-use teloxide::{utils::markdown::{bold, escape}, Bot};
+use teloxide::Bot;
 
 #[async_trait]
 pub trait UpdateProcessor: Sync + Send {
@@ -21,30 +28,47 @@ pub trait UpdateProcessor: Sync + Send {
         None
     }
 
-    async fn process(&self, bot: Bot, token: &Token) -> Result<(), BotError> {
+    fn settings_store(&self) -> &ChatSettingsStore;
+
+    /// Returns the chat which settings are applied to the reply.
+    fn settings_chat_id(&self) -> ChatId;
+
+    fn chat_settings(&self) -> ChatSettings {
+        self.settings_store().get(self.settings_chat_id())
+    }
+
+    fn video_policy(&self) -> VideoPolicy {
+        VideoPolicy::from(self.chat_settings().video_quality)
+    }
+
+    async fn process(&self, bot: Bot, source: &dyn TweetSource) -> Result<(), BotError> {
         match self.text_with_link() {
             Some(text) => {
                 let id = tweet_id_from_link(text)?;
-                return self.process_tweet(bot, id, token).await;
+                return self.process_tweet(bot, id, source).await;
             },
             _ => Ok(())
         }
     }
 
-    async fn process_tweet(&self, bot: Bot, id: u64, token: &Token) -> Result<(), BotError> {
+    async fn process_tweet(&self, bot: Bot, id: u64, source: &dyn TweetSource) -> Result<(), BotError> {
         self.track_hit_if_necessary().await?;
-        let tweet = tweet::show(id, &token).await?.response;
-        let reply = tweet_to_reply(&tweet).await?;
-        let id = format!("{}", tweet.id);
-        let included_in_thread = is_included_in_thread(&tweet, &token).await.unwrap_or(false);
-        self.answer(bot, id, reply, included_in_thread).await
+        let mut reply = source.reply(id).await?;
+        reply.apply_video_policy(&self.video_policy());
+        reply.apply_sensitive_media(self.chat_settings().sensitive_media);
+        let included_in_thread = source.is_included_in_thread(id).await.unwrap_or(false);
+        self.answer(bot, format!("{}", id), reply, included_in_thread).await
     }
 
-    async fn unroll_tweet(&self, bot: Bot, id: u64, token: &Token) -> Result<(), BotError> {
+    async fn unroll_tweet(&self, bot: Bot, id: u64, scope: ThreadScope, source: &dyn TweetSource) -> Result<(), BotError> {
         track_hit(String::from("unroll")).await?;
-        let tweet = tweet::show(id, &token).await?.response;
-        let reply = tweet_to_thread(&tweet, &token).await?;
-        self.send_thread_reply(&bot, format!("{}", tweet.id), reply, false).await
+        let mut reply = source.thread(id).await?;
+        if scope == ThreadScope::FromTweet {
+            reply.start_from(id)?;
+        }
+        reply.apply_video_policy(&self.video_policy());
+        reply.apply_sensitive_media(self.chat_settings().sensitive_media);
+        self.send_thread_reply(&bot, format!("{}", id), reply, false).await
     }
 
     async fn answer(&self, bot: Bot, id: String, reply: Reply, included_in_thread: bool) -> Result<(), BotError>;
@@ -54,91 +78,138 @@ pub trait UpdateProcessor: Sync + Send {
             return Ok(())
         }
 
+        let footer = match self.chat_settings().footer {
+            true => thread_reply.entities.first().map(|head| {
+                footer_text(thread_reply.screen_name.clone(), head.created_at, tweet_url(head.id, thread_reply.screen_name.as_deref()))
+            }),
+            false => None
+        };
+        let footer = match (thread_reply.earlier_tweets_only, footer) {
+            (true, Some(footer)) => Some(join(vec![earlier_tweets_notice(), footer], "\n\n")),
+            (true, None) => Some(earlier_tweets_notice()),
+            (false, footer) => footer
+        };
+
         let mut message_entity = thread_reply.entities.first().unwrap();
         let mut max_chunk_length = max_text_size(message_entity.media_entities.len() == 1);
 
-        let escaped_text = escape(message_entity.text.as_str());
-        let mut message_text = match thread_reply.user_name {
-            Some(name) => format!("{}\n\n{}", bold(escape(name.as_str()).as_str()), escaped_text),
-            None => escaped_text
+        let sensitive_media = self.chat_settings().sensitive_media;
+        let entity_text = thread_entity_text(message_entity, sensitive_media);
+        let header = header_text(thread_reply.user_name.clone(), thread_reply.screen_name.clone(), thread_reply.verified, thread_reply.created_at);
+        let mut message_text = match header {
+            Some(header) => join(vec![header, entity_text], "\n\n"),
+            None => entity_text
         };
 
         let mut did_edit_original_message = false;
 
         if thread_reply.entities.len() > 1 {
             for entity in &thread_reply.entities[1..] {
-                let entity_text = escape(entity.text.as_str());
-                let chunk_length = message_text.len() + 2 + entity_text.len();
+                let entity_text = thread_entity_text(entity, sensitive_media);
+                let chunk_length = rich_text_length(&message_text) + 2 + rich_text_length(&entity_text);
                 if entity.media_entities.len() > 0 || chunk_length > max_chunk_length {
-                    if did_edit_original_message {
-                        self.send_thread_entity(bot, message_entity, &message_text).await?;
-                    } else {
-                        self.edit_message_with_thread_entity(bot, message_entity, &message_text).await?;
-                        did_edit_original_message = true;
-                    }
+                    self.send_thread_chunk(bot, message_entity, &message_text, !did_edit_original_message).await?;
+                    did_edit_original_message = true;
                     message_entity = entity;
                     message_text = entity_text;
                     max_chunk_length = max_text_size(message_entity.media_entities.len() == 1);
                 } else {
-                    message_text = format!("{}\n\n{}", message_text, entity_text);
+                    message_text = join(vec![message_text, entity_text], "\n\n");
                 }
             }
         }
 
-        if did_edit_original_message {
-            self.send_thread_entity(bot, message_entity, &message_text).await?;
+        // The footer is sent separately if it doesn't fit into the last message.
+        let footer = match footer {
+            Some(footer) if rich_text_length(&message_text) + 2 + rich_text_length(&footer) <= max_chunk_length => {
+                message_text = join(vec![message_text, footer], "\n\n");
+                None
+            },
+            footer => footer
+        };
+
+        self.send_thread_chunk(bot, message_entity, &message_text, !did_edit_original_message).await?;
+
+        if let Some(footer) = footer {
+            self.send_text_thread_entity(bot, &footer).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends the entity with the chunk of a thread, or edits the original message with it.
+    /// A chunk which is still too long, e.g. a single long tweet, continues in text messages.
+    async fn send_thread_chunk(&self, bot: &Bot, entity: &ThreadEntity, text: &[RichText], edit: bool) -> Result<(), BotError> {
+        let (head, tail) = split_off_rich_text(text, max_text_size(entity.media_entities.len() == 1));
+        if edit {
+            self.edit_message_with_thread_entity(bot, entity, &head).await?;
         } else {
-            self.edit_message_with_thread_entity(bot, message_entity, &message_text).await?;
-        }  
+            self.send_thread_entity(bot, entity, &head).await?;
+        }
 
+        if let Some(tail) = tail {
+            self.send_text_thread_entity(bot, &tail).await?;
+        }
         Ok(())
     }
 
-    async fn edit_message_with_thread_entity(&self, bot: &Bot, entity: &ThreadEntity, escaped_text: &String) -> Result<(), BotError> { 
-        return self.send_thread_entity(bot, entity, escaped_text).await
+    async fn edit_message_with_thread_entity(&self, bot: &Bot, entity: &ThreadEntity, text: &[RichText]) -> Result<(), BotError> { 
+        return self.send_thread_entity(bot, entity, text).await
     }
 
-    async fn send_thread_entity(&self, bot: &Bot, entity: &ThreadEntity, escaped_text: &String) -> Result<(), BotError> {
+    async fn send_thread_entity(&self, bot: &Bot, entity: &ThreadEntity, text: &[RichText]) -> Result<(), BotError> {
         match entity.media_entities.len() {
-            0 => self.send_text_thread_entity(bot, &escaped_text).await,
-            1 => match entity.media_entities.first().unwrap() {
-                ParsedMedia::Image(image) => self.send_image_thread_entity(bot, image, &escaped_text).await,
-                ParsedMedia::Video(video) => self.send_video_thread_entity(bot, video, &escaped_text).await,
-            },
-            _ => self.send_media_group_thread_entity(bot, &entity, &escaped_text).await
+            0 => self.send_text_thread_entity(bot, text).await,
+            1 => self.send_media_with_text(bot, entity.media_entities.first().unwrap(), text, None).await,
+            _ => self.send_media_group_thread_entity(bot, &entity, text).await
         }
     }
 
-    async fn send_text_thread_entity(&self, bot: &Bot, escaped_text: &String) -> Result<(), BotError> {
-        bot.send_message(self.message_chat_id().unwrap(), escaped_text)
-        .parse_mode(ParseMode::MarkdownV2)
-        .disable_web_page_preview(true)
-        .await?;
+    async fn send_text_thread_entity(&self, bot: &Bot, text: &[RichText]) -> Result<(), BotError> {
+        for chunk in split_rich_text(text, max_text_size(false)) {
+            bot.send_message(self.message_chat_id().unwrap(), rendered(&chunk))
+            .parse_mode(PARSE_MODE)
+            .disable_web_page_preview(true)
+            .await?;
+        }
 
         Ok(())
     }
 
-    async fn send_image_thread_entity(&self, bot: &Bot, image: &ImageEntity, escaped_text: &String) -> Result<(), BotError> {
-        let video = InputFile::url(image.url.clone());
-        bot.send_photo(self.message_chat_id().unwrap(), video)
-        .caption(escaped_text)
-        .parse_mode(ParseMode::MarkdownV2)
-        .await?;
+    /// Sends a single media with the text as its caption. Text which doesn't fit into the caption is sent
+    /// as a separate message: after the media if leading paragraphs fit, otherwise before it.
+    async fn send_media_with_text(&self, bot: &Bot, media: &ParsedMedia, text: &[RichText], reply_markup: Option<InlineKeyboardMarkup>) -> Result<(), BotError> {
+        let chat_id = self.message_chat_id().unwrap();
+        let layout = caption_layout(text);
+        if let CaptionLayout::TextFirst(text) = &layout {
+            self.send_text_thread_entity(bot, text).await?;
+        }
 
-        Ok(())
-    }
+        let caption = rendered(&layout.caption());
+        match media {
+            ParsedMedia::Image(image) => {
+                let mut request = bot.send_photo(chat_id, InputFile::url(image.url.clone()))
+                .caption(caption.as_str())
+                .parse_mode(PARSE_MODE)
+                .has_spoiler(image.has_spoiler);
 
-    async fn send_video_thread_entity(&self, bot: &Bot, video: &VideoEntity, escaped_text: &String) -> Result<(), BotError> {
-        let video = InputFile::url(video.url.clone());
-        bot.send_video(self.message_chat_id().unwrap(), video)
-        .caption(escaped_text)
-        .parse_mode(ParseMode::MarkdownV2)
-        .await?;
+                if let Some(markup) = reply_markup {
+                    request = request.reply_markup(markup);
+                }
+
+                request.await?;
+            },
+            ParsedMedia::Video(video) => send_video(bot, chat_id, video, &caption, reply_markup).await?,
+            ParsedMedia::Animation(animation) => send_animation(bot, chat_id, animation, &caption, reply_markup).await?
+        }
 
+        if let CaptionLayout::Split { rest, .. } = &layout {
+            self.send_text_thread_entity(bot, rest).await?;
+        }
         Ok(())
     }
 
-    async fn send_media_group_thread_entity(&self, bot: &Bot, reply: &ThreadEntity, escaped_text: &String) -> Result<(), BotError> {
+    async fn send_media_group_thread_entity(&self, bot: &Bot, reply: &ThreadEntity, text: &[RichText]) -> Result<(), BotError> {
         let group = reply.media_entities.iter()
         .map(|media_entity| {
             match media_entity {
@@ -146,9 +217,10 @@ pub trait UpdateProcessor: Sync + Send {
                     media: InputFile::url(image.url.clone()),
                     caption: None,
                     parse_mode: None,
-                    caption_entities: None
+                    caption_entities: None,
+                    has_spoiler: image.has_spoiler
                 }),
-                ParsedMedia::Video(video) => InputMedia::Video(InputMediaVideo {
+                ParsedMedia::Video(video) | ParsedMedia::Animation(video) => InputMedia::Video(InputMediaVideo {
                     media: InputFile::url(video.url.clone()),
                     thumb: None,
                     caption: None,
@@ -158,30 +230,305 @@ pub trait UpdateProcessor: Sync + Send {
                     height: Some(video.height.try_into().unwrap()),
                     duration: None,
                     supports_streaming: None,
+                    has_spoiler: video.has_spoiler
                 })
             }            
         }).collect::<Vec<_>>();
         
         let chat_id = self.message_chat_id().unwrap();
         bot.send_media_group(chat_id, group).await?;
+        self.send_text_thread_entity(bot, text).await
+    }
 
-        bot.send_message(chat_id, escaped_text)
-        .parse_mode(ParseMode::MarkdownV2)
-        .disable_web_page_preview(true)
-        .await?;
-
+    /// Sends images of the reply as files, so Telegram doesn't recompress them.
+    /// Telegram fetches only GIF, PDF and ZIP documents by URL, so the images are downloaded and uploaded.
+    async fn send_original_images(&self, bot: &Bot, reply: &Reply) -> Result<(), BotError> {
+        let chat_id = self.message_chat_id().unwrap();
+        let mut downloads = vec![];
+        for image in reply.displayed_images() {
+            let url = image.original_url();
+            match download_file(&url, MAX_UPLOAD_SIZE).await? {
+                Some(path) => downloads.push((path, url.path_segments().and_then(|mut s| s.next_back()).unwrap_or("image.jpg").to_string())),
+                None => log::info!("Original image {} exceeds {} bytes", url, MAX_UPLOAD_SIZE)
+            }
+        }
+        let files = downloads.iter()
+        .map(|(path, name)| InputFile::file(path.to_path_buf()).file_name(name.clone()))
+        .collect::<Vec<_>>();
+        match files.len() {
+            0 => {},
+            1 => { bot.send_document(chat_id, files[0].clone()).await?; },
+            _ => {
+                let group = files.into_iter().map(|file| InputMedia::Document(InputMediaDocument::new(file))).collect::<Vec<_>>();
+                bot.send_media_group(chat_id, group).await?;
+            }
+        }
         Ok(())
     }
 
     async fn track_hit_if_necessary(&self) -> Result<(), BotError>;
 }
 
-/// Returns escaped text with the user name as a bold title.
-pub fn escaped_text<T>(data: &T) -> String where T: ReplyData {
-    let escaped_text = escape(data.text().as_str());
-    match data.user_name() {
-        Some(name) => format!("{}\n\n{}", bold(escape(name.as_str()).as_str()), escaped_text),
-        None => escaped_text
+/// Parse mode of all formatted messages.
+pub const PARSE_MODE: ParseMode = ParseMode::Html;
+
+/// Renders the text as markup of [`PARSE_MODE`].
+pub fn rendered(text: &[RichText]) -> String {
+    render(text, PARSE_MODE)
+}
+
+/// Returns rendered text with the author header as a title.
+pub fn escaped_text<T>(data: &T, settings: &ChatSettings) -> String where T: ReplyData {
+    rendered(&reply_text(data, settings))
+}
+
+/// Returns the text of the reply with the author header as a title.
+pub fn reply_text<T>(data: &T, settings: &ChatSettings) -> Vec<RichText> where T: ReplyData {
+    // Alt texts and the card describe the media, so they're hidden with the text.
+    let mut content = vec![data.rich_text()];
+    if let Some(alt_texts) = alt_texts_text(&data.alt_texts()) {
+        content.push(alt_texts);
+    }
+    if let Some(card) = data.card().and_then(card_text) {
+        content.push(card);
+    }
+    let mut paragraphs = vec![sensitive_text(join(content, "\n\n"), data.sensitive(), settings.sensitive_media)];
+    if let Some(poll) = data.poll() {
+        paragraphs.push(poll_text(poll));
+    }
+    if let (true, Some(metrics)) = (settings.metrics, data.metrics()) {
+        paragraphs.push(metrics_text(&metrics));
+    }
+    if let Some(header) = reply_header_text(data) {
+        paragraphs.insert(0, header);
+    }
+    if let Some(quoted) = data.quoted() {
+        paragraphs.push(quote_text(quoted, settings.sensitive_media));
+    }
+    if let (true, Some(url)) = (settings.footer, data.tweet_url()) {
+        paragraphs.push(footer_text(data.screen_name(), data.created_at(), url));
+    }
+
+    let text = join(paragraphs, "\n\n");
+    match data.retweeted_by() {
+        Some(name) => join(vec![vec![RichText::from("🔁 "), RichText::Italic(vec![RichText::from(format!("Retweeted by {}", name))])], text], "\n"),
+        None => text
+    }
+}
+
+/// Returns a line with the author handle, the creation date and a link to the tweet.
+pub fn footer_text(screen_name: Option<String>, created_at: Option<DateTime<Utc>>, url: Url) -> Vec<RichText> {
+    let mut parts = vec![];
+    if let Some(screen_name) = screen_name {
+        parts.push(vec![RichText::from(format!("@{}", screen_name))]);
+    }
+    if let Some(created_at) = created_at {
+        parts.push(vec![RichText::from(format_date(&created_at))]);
+    }
+    parts.push(vec![RichText::Link(url.to_string(), vec![RichText::from("Open tweet")])]);
+    join(parts, " · ")
+}
+
+/// Tells that later tweets of a thread missed by the conversation search couldn't be loaded.
+pub fn earlier_tweets_notice() -> Vec<RichText> {
+    vec![RichText::Italic(vec![RichText::from("⚠️ Only this tweet and earlier ones of the thread could be loaded")])]
+}
+
+/// Hides the text of a sensitive tweet behind a spoiler or replaces it with a notice.
+pub fn sensitive_text(text: Vec<RichText>, sensitive: bool, mode: SensitiveMedia) -> Vec<RichText> {
+    match (sensitive, mode) {
+        (true, SensitiveMedia::Spoiler) if rich_text_length(&text) > 0 => vec![RichText::Spoiler(text)],
+        (true, SensitiveMedia::Block) => vec![RichText::Italic(vec![RichText::from("⚠️ Sensitive content is hidden in this chat")])],
+        _ => text
+    }
+}
+
+/// Returns the text of a thread tweet followed by alt texts of its images.
+fn thread_entity_text(entity: &ThreadEntity, sensitive_media: SensitiveMedia) -> Vec<RichText> {
+    let text = match alt_texts_text(&image_alt_texts(&entity.media_entities.iter().collect::<Vec<_>>())) {
+        Some(alt_texts) => join(vec![entity.rich_text(), alt_texts], "\n\n"),
+        None => entity.rich_text()
+    };
+    sensitive_text(text, entity.sensitive, sensitive_media)
+}
+
+/// Returns a line per image with alt text, e.g. "🖼 Image 2: Ferris waving". Images are numbered only if there are several.
+pub fn alt_texts_text(alt_texts: &[Option<String>]) -> Option<Vec<RichText>> {
+    let lines = alt_texts.iter().enumerate()
+    .filter_map(|(index, alt_text)| {
+        let alt_text = alt_text.as_ref()?;
+        match alt_texts.len() {
+            1 => Some(format!("🖼 Image: {}", alt_text)),
+            _ => Some(format!("🖼 Image {}: {}", index + 1, alt_text))
+        }
+    })
+    .collect::<Vec<_>>();
+    match lines.is_empty() {
+        true => None,
+        false => Some(vec![RichText::from(lines.join("\n"))])
+    }
+}
+
+/// Returns the title and the description of a card sent with its image. Cards without an image are shown as web page previews.
+pub fn card_text(card: &LinkCard) -> Option<Vec<RichText>> {
+    card.image.as_ref()?;
+    let mut lines = vec![];
+    if let Some(title) = &card.title {
+        lines.push(vec![RichText::from("🔗 "), RichText::Link(card.url.to_string(), vec![RichText::Bold(vec![RichText::from(title.as_str())])])]);
+    }
+    if let Some(description) = &card.description {
+        lines.push(vec![RichText::from(description.as_str())]);
+    }
+    match lines.is_empty() {
+        true => None,
+        false => Some(join(lines, "\n"))
+    }
+}
+
+/// Prepends an invisible link to the card URL, so Telegram previews it instead of other links in the text.
+/// Returns the text and whether the web page preview should be disabled.
+pub fn text_with_preview(reply: &Reply, text: Vec<RichText>) -> (Vec<RichText>, bool) {
+    match reply.preview_url() {
+        Some(url) => {
+            let mut text = text;
+            text.insert(0, RichText::Link(url.to_string(), vec![RichText::from("\u{200B}")]));
+            (text, false)
+        },
+        None => (text, true)
+    }
+}
+
+/// Returns the poll status line followed by options with their shares of votes.
+pub fn poll_text(poll: &Poll) -> Vec<RichText> {
+    let status = match (poll.open, poll.end_datetime) {
+        (true, Some(end_datetime)) => format!("Open until {}", format_date(&end_datetime)),
+        (true, None) => String::from("Open"),
+        (false, _) => String::from("Final results")
+    };
+    let mut lines = vec![format!("📊 {} votes · {}", compact_number(poll.total_votes()), status)];
+    for option in &poll.options {
+        lines.push(format!("▫️ {} — {}%", option.label, poll.percentage(option)));
+    }
+    vec![RichText::from(lines.join("\n"))]
+}
+
+/// Returns a line like "❤️ 12.3K  🔁 1.2K  💬 340  👁 1.1M".
+pub fn metrics_text(metrics: &TweetMetrics) -> Vec<RichText> {
+    let mut parts = vec![
+        format!("❤️ {}", compact_number(metrics.likes)),
+        format!("🔁 {}", compact_number(metrics.retweets)),
+    ];
+    if let Some(replies) = metrics.replies {
+        parts.push(format!("💬 {}", compact_number(replies)));
+    }
+    if let Some(views) = metrics.views {
+        parts.push(format!("👁 {}", compact_number(views)));
+    }
+    vec![RichText::from(parts.join("  "))]
+}
+
+/// Formats a number with one decimal and a K/M/B suffix, e.g. 12345 as 12.3K. Decimals are truncated, not rounded.
+pub fn compact_number(value: i64) -> String {
+    let (divisor, suffix) = match value.abs() {
+        0..=999 => return value.to_string(),
+        1_000..=999_999 => (1_000, "K"),
+        1_000_000..=999_999_999 => (1_000_000, "M"),
+        _ => (1_000_000_000, "B")
+    };
+    let tenths = value / (divisor / 10);
+    match tenths % 10 {
+        0 => format!("{}{}", tenths / 10, suffix),
+        _ => format!("{}.{}{}", tenths / 10, (tenths % 10).abs(), suffix)
+    }
+}
+
+pub fn format_date(date: &DateTime<Utc>) -> String {
+    date.format("%-d %b %Y, %H:%M UTC").to_string()
+}
+
+fn reply_header_text<T>(data: &T) -> Option<Vec<RichText>> where T: ReplyData {
+    header_text(data.user_name(), data.screen_name(), data.verified(), data.created_at())
+}
+
+/// Returns the header of a reply in the form of "Name (@handle) · date" with the bold name.
+pub fn header_text(user_name: Option<String>, screen_name: Option<String>, verified: bool, created_at: Option<DateTime<Utc>>) -> Option<Vec<RichText>> {
+    let mut author = vec![];
+    if let Some(name) = user_name {
+        author.push(vec![RichText::Bold(vec![RichText::from(name)])]);
+    }
+    if verified {
+        author.push(vec![RichText::from("☑️")]);
+    }
+    if let Some(screen_name) = screen_name {
+        author.push(vec![RichText::from(format!("(@{})", screen_name))]);
+    }
+    if author.is_empty() {
+        return None
+    }
+
+    let author = join(author, " ");
+    match created_at {
+        Some(created_at) => Some(join(vec![author, vec![RichText::from(created_at.format("%-d %b %Y").to_string())]], " · ")),
+        None => Some(author)
+    }
+}
+
+/// Returns the quoted tweet as a quote block with its header.
+pub fn quote_text(quoted: &Reply, sensitive_media: SensitiveMedia) -> Vec<RichText> {
+    let mut lines = vec![];
+    if let Some(header) = reply_header_text(quoted) {
+        lines.push(header);
+    }
+    lines.push(sensitive_text(quoted.rich_text(), quoted.sensitive, sensitive_media));
+    vec![RichText::Quote(join(lines, "\n"))]
+}
+
+/// Placement of a text sent together with a single media.
+#[derive(Debug, PartialEq)]
+pub enum CaptionLayout {
+    /// The whole text fits into the caption.
+    Caption(Vec<RichText>),
+    /// Leading paragraphs go to the caption and the rest follows the media as a text message.
+    Split { caption: Vec<RichText>, rest: Vec<RichText> },
+    /// The text is sent as a message before the media, which has no caption.
+    TextFirst(Vec<RichText>),
+}
+
+impl CaptionLayout {
+    pub fn caption(&self) -> Vec<RichText> {
+        match self {
+            CaptionLayout::Caption(caption) | CaptionLayout::Split { caption, .. } => caption.clone(),
+            CaptionLayout::TextFirst(_) => vec![]
+        }
+    }
+}
+
+/// Splits the text between a caption and a text message at a paragraph boundary.
+/// A caption with only the first paragraph (the header) isn't worth it, so the text goes first then.
+pub fn caption_layout(text: &[RichText]) -> CaptionLayout {
+    let max_length = max_text_size(true);
+    if rich_text_length(text) <= max_length {
+        return CaptionLayout::Caption(text.to_vec());
+    }
+
+    let paragraphs = split_paragraphs(text);
+    let mut count = 0;
+    let mut length = 0;
+    for paragraph in &paragraphs {
+        let separator_length = if count > 0 { 2 } else { 0 };
+        if length + separator_length + rich_text_length(paragraph) > max_length {
+            break;
+        }
+        length += separator_length + rich_text_length(paragraph);
+        count += 1;
+    }
+
+    match count {
+        0 | 1 => CaptionLayout::TextFirst(text.to_vec()),
+        _ => CaptionLayout::Split {
+            caption: join(paragraphs[..count].to_vec(), "\n\n"),
+            rest: join(paragraphs[count..].to_vec(), "\n\n")
+        }
     }
 }
 
@@ -190,4 +537,84 @@ pub fn max_text_size(caption: bool) -> usize {
         true => 1024,
         false => 4096
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_compact_numbers() {
+        assert_eq!(compact_number(0), "0");
+        assert_eq!(compact_number(999), "999");
+        assert_eq!(compact_number(1_000), "1K");
+        assert_eq!(compact_number(12_345), "12.3K");
+        assert_eq!(compact_number(999_999), "999.9K");
+        assert_eq!(compact_number(1_050_000), "1M");
+        assert_eq!(compact_number(1_250_000), "1.2M");
+        assert_eq!(compact_number(3_400_000_000), "3.4B");
+    }
+
+    #[test]
+    fn renders_metrics_line() {
+        let metrics = TweetMetrics { likes: 12_345, retweets: 987, replies: Some(1_200), views: None };
+        assert_eq!(rendered(&metrics_text(&metrics)), "❤️ 12.3K  🔁 987  💬 1.2K");
+    }
+
+    #[test]
+    fn renders_header_template() {
+        let date = "2022-11-03T15:00:12Z".parse::<DateTime<Utc>>().unwrap();
+        let header = header_text(Some(String::from("Rust & Friends")), Some(String::from("rustlang")), true, Some(date));
+        assert_eq!(rendered(&header.unwrap()), "<b>Rust &amp; Friends</b> ☑️ (@rustlang) · 3 Nov 2022");
+    }
+
+    #[test]
+    fn renders_header_without_missing_parts() {
+        let header = header_text(Some(String::from("Rust_Lang")), None, false, None);
+        assert_eq!(rendered(&header.unwrap()), "<b>Rust_Lang</b>");
+        assert_eq!(header_text(None, None, false, None), None);
+    }
+
+    #[test]
+    fn renders_alt_texts() {
+        let single = alt_texts_text(&[Some(String::from("Ferris <the crab>"))]);
+        assert_eq!(rendered(&single.unwrap()), "🖼 Image: Ferris &lt;the crab&gt;");
+
+        let several = alt_texts_text(&[None, Some(String::from("A chart")), Some(String::from("A logo"))]);
+        assert_eq!(rendered(&several.unwrap()), "🖼 Image 2: A chart\n🖼 Image 3: A logo");
+        assert_eq!(alt_texts_text(&[None, None]), None);
+    }
+
+    #[test]
+    fn lays_out_long_captions() {
+        let header = vec![RichText::Bold(vec![RichText::from("Ferris")]), RichText::from(" (@ferris)")];
+        assert_eq!(caption_layout(&header), CaptionLayout::Caption(header.clone()));
+
+        let body = vec![RichText::Spoiler(vec![RichText::from("🦀".repeat(400))])];
+        let alt = vec![RichText::from("a".repeat(300))];
+        let text = join(vec![header.clone(), body.clone(), alt.clone()], "\n\n");
+        assert_eq!(caption_layout(&text), CaptionLayout::Split {
+            caption: join(vec![header.clone(), body], "\n\n"),
+            rest: alt
+        });
+
+        // Emoji take two UTF-16 code units, so the body doesn't fit alongside the header.
+        let long_body = vec![RichText::from("🦀".repeat(510))];
+        let text = join(vec![header, long_body], "\n\n");
+        assert_eq!(caption_layout(&text), CaptionLayout::TextFirst(text.clone()));
+        assert_eq!(caption_layout(&text).caption(), vec![]);
+    }
+
+    #[test]
+    fn keeps_spoiler_across_caption_and_rest() {
+        let header = vec![RichText::from("Header")];
+        let body = vec![RichText::Spoiler(vec![RichText::from(format!("{}\n\n{}", "a".repeat(600), "b".repeat(600)))])];
+        match caption_layout(&join(vec![header, body], "\n\n")) {
+            CaptionLayout::Split { caption, rest } => {
+                assert!(rendered(&caption).ends_with("a</tg-spoiler>"));
+                assert!(rendered(&rest).starts_with("<tg-spoiler>b"));
+            },
+            layout => panic!("Unexpected layout {:?}", layout)
+        }
+    }
+}
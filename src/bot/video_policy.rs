@@ -0,0 +1,170 @@
+use std::fmt;
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::parser::VideoVariant;
+use crate::video_uploader::MAX_UPLOAD_SIZE;
+
+/// Preferred video quality. Limits the smaller side of the video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoQuality {
+    #[default]
+    #[serde(rename = "best")]
+    Best,
+    #[serde(rename = "1080p")]
+    P1080,
+    #[serde(rename = "720p")]
+    P720,
+    #[serde(rename = "480p")]
+    P480,
+    #[serde(rename = "360p")]
+    P360,
+}
+
+impl VideoQuality {
+    pub fn max_resolution(&self) -> Option<i32> {
+        match self {
+            VideoQuality::Best => None,
+            VideoQuality::P1080 => Some(1080),
+            VideoQuality::P720 => Some(720),
+            VideoQuality::P480 => Some(480),
+            VideoQuality::P360 => Some(360),
+        }
+    }
+}
+
+impl FromStr for VideoQuality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "best" => Ok(VideoQuality::Best),
+            "1080p" | "1080" => Ok(VideoQuality::P1080),
+            "720p" | "720" => Ok(VideoQuality::P720),
+            "480p" | "480" => Ok(VideoQuality::P480),
+            "360p" | "360" => Ok(VideoQuality::P360),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for VideoQuality {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max_resolution() {
+            Some(resolution) => write!(f, "{}p", resolution),
+            None => write!(f, "best")
+        }
+    }
+}
+
+/// Chooses a video variant by resolution and size estimated from the bitrate and duration.
+pub struct VideoPolicy {
+    /// Upper bound for the smaller side of the video.
+    pub max_resolution: Option<i32>,
+    /// Upper bound for the estimated file size in bytes.
+    pub max_size: u64,
+}
+
+impl Default for VideoPolicy {
+    fn default() -> Self {
+        VideoPolicy::from(VideoQuality::default())
+    }
+}
+
+impl From<VideoQuality> for VideoPolicy {
+    fn from(quality: VideoQuality) -> Self {
+        VideoPolicy {
+            max_resolution: quality.max_resolution(),
+            max_size: MAX_UPLOAD_SIZE,
+        }
+    }
+}
+
+impl VideoPolicy {
+    /// Returns an index of the best variant matching the policy. Variants are expected to be sorted
+    /// from the highest bitrate to the lowest. The lowest variant is chosen if none matches.
+    pub fn select(&self, variants: &[VideoVariant], duration_ms: Option<i32>) -> Option<usize> {
+        if variants.is_empty() {
+            return None;
+        }
+
+        let index = variants.iter().position(|variant| {
+            let fits_resolution = match (self.max_resolution, variant.resolution()) {
+                (Some(max), Some((width, height))) => width.min(height) <= max,
+                _ => true
+            };
+            let fits_size = match estimated_size(variant.bitrate, duration_ms) {
+                Some(size) => size <= self.max_size,
+                None => true
+            };
+            fits_resolution && fits_size
+        });
+
+        Some(index.unwrap_or(variants.len() - 1))
+    }
+}
+
+/// Returns the estimated size in bytes of a video with the bitrate in bits per second.
+pub fn estimated_size(bitrate: Option<i32>, duration_ms: Option<i32>) -> Option<u64> {
+    match (bitrate, duration_ms) {
+        (Some(bitrate), Some(duration_ms)) if bitrate > 0 && duration_ms > 0 => Some(bitrate as u64 * duration_ms as u64 / 8000),
+        _ => None
+    }
+}
+
+impl VideoVariant {
+    /// Returns the variant resolution from Twitter video URLs like `.../vid/1280x720/...`.
+    pub fn resolution(&self) -> Option<(i32, i32)> {
+        let regex = Regex::new(r"/(\d+)x(\d+)/").ok()?;
+        let caps = regex.captures(self.url.path())?;
+        Some((caps.get(1)?.as_str().parse().ok()?, caps.get(2)?.as_str().parse().ok()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Url;
+
+    fn variant(resolution: &str, bitrate: i32) -> VideoVariant {
+        VideoVariant {
+            url: Url::parse(format!("https://video.twimg.com/ext_tw_video/1/pu/vid/{}/a.mp4?tag=12", resolution).as_str()).unwrap(),
+            bitrate: Some(bitrate)
+        }
+    }
+
+    fn variants() -> Vec<VideoVariant> {
+        vec![variant("1920x1080", 10368000), variant("1280x720", 2176000), variant("640x360", 832000), variant("480x270", 256000)]
+    }
+
+    #[test]
+    fn selects_best_variant_by_default() {
+        assert_eq!(VideoPolicy::default().select(&variants(), Some(30_000)), Some(0));
+    }
+
+    #[test]
+    fn limits_resolution() {
+        assert_eq!(VideoPolicy::from(VideoQuality::P720).select(&variants(), Some(30_000)), Some(1));
+        assert_eq!(VideoPolicy::from(VideoQuality::P480).select(&variants(), Some(30_000)), Some(2));
+        assert_eq!(VideoPolicy::from(VideoQuality::P720).select(&[variant("720x1280", 2176000)], None), Some(0));
+    }
+
+    #[test]
+    fn limits_estimated_size() {
+        // 10 Mbit/s for 60 seconds is about 78 MB.
+        assert_eq!(VideoPolicy::default().select(&variants(), Some(60_000)), Some(1));
+        // Nothing fits in 1 KB, so the smallest variant is chosen.
+        let policy = VideoPolicy { max_resolution: None, max_size: 1024 };
+        assert_eq!(policy.select(&variants(), Some(60_000)), Some(3));
+    }
+
+    #[test]
+    fn parses_quality() {
+        assert_eq!("720p".parse(), Ok(VideoQuality::P720));
+        assert_eq!("BEST".parse(), Ok(VideoQuality::Best));
+        assert_eq!(VideoQuality::P480.to_string(), "480p");
+        assert!("4k".parse::<VideoQuality>().is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::io::Write;
+use std::path::Path;
+
+use teloxide::payloads::{SendAnimationSetters, SendVideoSetters};
+use teloxide::requests::Requester;
+use teloxide::types::{ChatId, InlineKeyboardMarkup, InputFile};
+use teloxide::{ApiError, Bot, RequestError};
+use reqwest::Url;
+use tempfile::TempPath;
+
+use crate::bot_errors::{BotError, BotErrorKind};
+use crate::parser::VideoEntity;
+use crate::update_processor::PARSE_MODE;
+
+/// Telegram accepts uploaded videos up to 50 MB.
+pub const MAX_UPLOAD_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Sends a video by its URL. When Telegram can't fetch the URL itself (e.g. files over 20 MB),
+/// the video is downloaded and uploaded as a file.
+pub async fn send_video(bot: &Bot, chat_id: ChatId, video: &VideoEntity, escaped_text: &String, reply_markup: Option<InlineKeyboardMarkup>) -> Result<(), BotError> {
+    match send_video_file(bot, chat_id, InputFile::url(video.url.clone()), video.has_spoiler, escaped_text, reply_markup.clone()).await {
+        Err(RequestError::Api(error)) if is_url_fetch_error(&error) => {
+            log::info!("Telegram couldn't fetch {}, uploading the video", video.url);
+            let path = download_video(video, MAX_UPLOAD_SIZE).await?;
+            Ok(send_video_file(bot, chat_id, InputFile::file(path.to_path_buf()), video.has_spoiler, escaped_text, reply_markup).await?)
+        },
+        result => Ok(result?)
+    }
+}
+
+async fn send_video_file(bot: &Bot, chat_id: ChatId, file: InputFile, has_spoiler: bool, escaped_text: &String, reply_markup: Option<InlineKeyboardMarkup>) -> Result<(), RequestError> {
+    let mut request = bot.send_video(chat_id, file)
+    .caption(escaped_text)
+    .parse_mode(PARSE_MODE)
+    .has_spoiler(has_spoiler);
+
+    if let Some(markup) = reply_markup {
+        request = request.reply_markup(markup);
+    }
+
+    request.await?;
+    Ok(())
+}
+
+/// Sends an animated GIF by its URL. Twitter keeps GIFs small, so they aren't uploaded as a fallback.
+pub async fn send_animation(bot: &Bot, chat_id: ChatId, animation: &VideoEntity, escaped_text: &String, reply_markup: Option<InlineKeyboardMarkup>) -> Result<(), BotError> {
+    let mut request = bot.send_animation(chat_id, InputFile::url(animation.url.clone()))
+    .caption(escaped_text)
+    .parse_mode(PARSE_MODE)
+    .has_spoiler(animation.has_spoiler);
+
+    if let Some(markup) = reply_markup {
+        request = request.reply_markup(markup);
+    }
+
+    request.await?;
+    Ok(())
+}
+
+fn is_url_fetch_error(error: &ApiError) -> bool {
+    match error {
+        ApiError::WrongFileIdOrUrl | ApiError::FailedToGetUrlContent => true,
+        ApiError::Unknown(description) => description.contains("HTTP URL") || description.contains("web page content"),
+        _ => false
+    }
+}
+
+/// Downloads the first variant which fits into `max_size` bytes, starting from the highest bitrate.
+/// The file is removed when the returned path is dropped.
+pub async fn download_video(video: &VideoEntity, max_size: u64) -> Result<TempPath, BotError> {
+    for variant in &video.variants {
+        if let Some(path) = download_file(&variant.url, max_size).await? {
+            return Ok(path);
+        }
+        log::info!("Video variant {} exceeds {} bytes", variant.url, max_size);
+    }
+    Err(BotError::from(BotErrorKind::VideoTooLarge))
+}
+
+/// Downloads the file to a temporary path with the same extension. Returns `None` if it exceeds `max_size` bytes.
+pub async fn download_file(url: &Url, max_size: u64) -> Result<Option<TempPath>, BotError> {
+    let mut response = reqwest::get(url.clone()).await?.error_for_status()?;
+    if response.content_length().map(|length| length > max_size).unwrap_or(false) {
+        return Ok(None);
+    }
+
+    let suffix = Path::new(url.path()).extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    let mut file = tempfile::Builder::new().suffix(suffix.as_str()).tempfile()?;
+    let mut size = 0;
+    while let Some(chunk) = response.chunk().await? {
+        size += chunk.len() as u64;
+        if size > max_size {
+            return Ok(None);
+        }
+        file.write_all(&chunk)?;
+    }
+    Ok(Some(file.into_temp_path()))
+}
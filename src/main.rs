@@ -1,16 +1,16 @@
 use std::env;
 use std::error::Error;
 use std::string::String;
+use std::sync::Arc;
 
 use bot::callback_query_processor::CallbackQueryProcessor;
 use teloxide::dispatching::DefaultKey;
 use teloxide::{prelude::*, RequestError};
 use teloxide::types::*;
-use egg_mode::*;
 
-use bot::bot_errors::{BotError};
+use bot::chat_settings::ChatSettingsStore;
 use bot::webhook::webhook;
-use bot::twitter_utils::twitter_api_token_value;
+use bot::tweet_source::{tweet_source_from_env, TweetSource};
 use bot::update_processor::UpdateProcessor;
 use bot::text_message_processor::TextMessageProcessor;
 use bot::inline_query_processor::InlineQueryProcessor;
@@ -23,10 +23,11 @@ async fn main() {
     let is_webhooks_enabled = env::var("WEBHOOKS_ENABLED").expect("WEBHOOKS_ENABLED not set");
 
     let bot = Bot::from_env();
+    let source = tweet_source_from_env().await.expect("Failed to create a tweet source");
 
     if is_webhooks_enabled.to_lowercase() == String::from("true") {
         log::info!("Creating a webhook...");
-        dispatcher(bot.clone())
+        dispatcher(bot.clone(), source)
         .dispatch_with_listener(
             webhook(bot).await,
             LoggingErrorHandler::with_custom_text("An error from the update listener"),
@@ -34,40 +35,40 @@ async fn main() {
         .await;
     } else {
         log::info!("Starting polling...");
-        dispatcher(bot.clone())
+        dispatcher(bot.clone(), source)
         .dispatch()
         .await;
     }
 }
 
-fn dispatcher(bot: Bot) -> Dispatcher<Bot, RequestError, DefaultKey> {
+fn dispatcher(bot: Bot, source: Arc<dyn TweetSource>) -> Dispatcher<Bot, RequestError, DefaultKey> {
     let handler = dptree::entry()
-    .branch(Update::filter_message().endpoint(|bot: Bot, msg: Message, _me: Me| async {
-        process_message(bot, msg).await.log_on_error().await;
+    .branch(Update::filter_message().endpoint(|bot: Bot, msg: Message, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>, me: Me| async move {
+        process_message(bot, msg, settings, source, me).await.log_on_error().await;
         respond(())
     }))
-    .branch(Update::filter_inline_query().endpoint(|bot: Bot, q: InlineQuery| async {
-        process_inline_query(bot, q).await.log_on_error().await;
+    .branch(Update::filter_inline_query().endpoint(|bot: Bot, q: InlineQuery, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>| async move {
+        process_inline_query(bot, q, settings, source).await.log_on_error().await;
         respond(())
     }))
-    .branch(Update::filter_callback_query().endpoint(|bot: Bot, q: CallbackQuery| async {
-        process_callback_query(bot, q).await.log_on_error().await;
+    .branch(Update::filter_callback_query().endpoint(|bot: Bot, q: CallbackQuery, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>| async move {
+        process_callback_query(bot, q, settings, source).await.log_on_error().await;
         respond(())
     }));
 
     Dispatcher::builder(bot, handler)
+    .dependencies(dptree::deps![Arc::new(ChatSettingsStore::from_env()), source])
     .enable_ctrlc_handler()
     .build()
 }
 
 
-async fn process_message(bot: Bot, message: Message) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn process_message(bot: Bot, message: Message, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>, me: Me) -> Result<(), Box<dyn Error + Send + Sync>> {
     log::info!("Received a message");
-    let token = twitter_token_data().await?;
     match message_text(&message) {
         Some(text) => {
-            let processor = TextMessageProcessor {  message: message, text: text };
-            return Ok(processor.process(bot, &token).await?);
+            let processor = TextMessageProcessor {  message: message, text: text, settings: settings, bot_name: me.username().to_string() };
+            return Ok(processor.process(bot, source.as_ref()).await?);
         },
         _ => Ok(())
     }
@@ -85,31 +86,14 @@ fn message_text(message: &Message) -> Option<String> {
     }
 }
 
-async fn process_inline_query(bot: Bot, query: InlineQuery) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn process_inline_query(bot: Bot, query: InlineQuery, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>) -> Result<(), Box<dyn Error + Send + Sync>> {
     log::info!("Received an inline query");
-    let token = twitter_token_data().await?;
-    let processor = InlineQueryProcessor { query: query };
-    return Ok(processor.process(bot, &token).await?);
+    let processor = InlineQueryProcessor { query: query, settings: settings };
+    return Ok(processor.process(bot, source.as_ref()).await?);
 }
 
-async fn process_callback_query(bot: Bot, query: CallbackQuery) -> Result<(), Box<dyn Error + Send + Sync>> {
+async fn process_callback_query(bot: Bot, query: CallbackQuery, settings: Arc<ChatSettingsStore>, source: Arc<dyn TweetSource>) -> Result<(), Box<dyn Error + Send + Sync>> {
     log::info!("Received a callback query");
-    let token = twitter_token_data().await?;
-    let processor = CallbackQueryProcessor { query: query };
-    return Ok(processor.process(bot, &token).await?);
+    let processor = CallbackQueryProcessor { query: query, settings: settings };
+    return Ok(processor.process(bot, source.as_ref()).await?);
 }
-
-async fn twitter_token_data() -> Result<Token, BotError> {
-    if let Ok(token_value) = env::var("TWITTER_BEARER_TOKEN") {
-        let token = Token::Bearer(token_value);
-        return Ok(token);
-    }
-
-    let twitter_client_id = env::var("TWITTER_CLIENT_ID").expect("TWITTER_CLIENT_ID not set");
-    let twitter_secret = env::var("TWITTER_SECRET").expect("TWITTER_SECRET not set");
-    let token = twitter_api_token_value(twitter_client_id, twitter_secret).await?;
-    if let Token::Bearer(token_value) = &token {
-        env::set_var("TWITTER_BEARER_TOKEN", token_value);
-    }
-    Ok(token)
-}
\ No newline at end of file
@@ -0,0 +1,382 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bot::fixture_tweet_source::FixtureTweetSource;
+use bot::twitter_utils::TwitterApi;
+use egg_mode::Token;
+use reqwest::Url;
+use serde_json::{json, Value};
+use teloxide::Bot;
+use wiremock::matchers::{any, method, path, path_regex, query_param};
+use wiremock::{Match, Mock, MockServer, Request, Respond, ResponseTemplate};
+
+pub fn fixtures_path(dir: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(dir)
+}
+
+pub fn fixture(dir: &str, name: &str) -> String {
+    fs::read_to_string(fixtures_path(dir).join(name)).expect("Missed fixture")
+}
+
+fn json_response(body: String) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_raw(body, "application/json")
+}
+
+/// In-process stand-in for Twitter API serving recorded payloads from `tests/fixtures/twitter`:
+/// * `v1/<id>.json` for `/1.1/statuses/show.json?id=<id>`;
+/// * `v2/tweets_<id>.json` for `/2/tweets/<id>`, keeping only the media of the requested expansions;
+/// * `v2/search_<conversation_id>.json` for `/2/tweets/search/recent` with a `conversation_id:<conversation_id>` query;
+/// * `v2/archive_<conversation_id>.json` for `/2/tweets/search/all` with the same query searching since the first tweet.
+pub struct TwitterMock {
+    pub server: MockServer
+}
+
+impl TwitterMock {
+    pub async fn start() -> TwitterMock {
+        let server = MockServer::start().await;
+
+        for (name, body) in fixture_files("twitter/v1") {
+            Mock::given(method("GET"))
+            .and(path("/1.1/statuses/show.json"))
+            .and(query_param("id", name.as_str()))
+            .respond_with(json_response(body))
+            .mount(&server)
+            .await;
+        }
+
+        for (name, body) in fixture_files("twitter/v2") {
+            if let Some(id) = name.strip_prefix("tweets_") {
+                Mock::given(method("GET"))
+                .and(path(format!("/2/tweets/{}", id)))
+                .respond_with(TweetLookupResponder(serde_json::from_str(body.as_str()).unwrap()))
+                .mount(&server)
+                .await;
+            } else if let Some(conversation_id) = name.strip_prefix("search_") {
+                Mock::given(method("GET"))
+                .and(path("/2/tweets/search/recent"))
+                .and(ConversationQuery(String::from(conversation_id)))
+                .respond_with(json_response(body))
+                .mount(&server)
+                .await;
+            } else if let Some(conversation_id) = name.strip_prefix("archive_") {
+                Mock::given(method("GET"))
+                .and(path("/2/tweets/search/all"))
+                .and(query_param("start_time", "2006-03-21T00:00:00Z"))
+                .and(ConversationQuery(String::from(conversation_id)))
+                .respond_with(json_response(body))
+                .mount(&server)
+                .await;
+            }
+        }
+
+        TwitterMock { server }
+    }
+
+    /// Makes v2 tweet lookups fail the way they do when the rate limit is exceeded.
+    pub async fn reject_v2_lookups(&self) {
+        Mock::given(method("GET"))
+        .and(path_regex(r"^/2/tweets/\d+$"))
+        .respond_with(ResponseTemplate::new(429))
+        .with_priority(1)
+        .mount(&self.server)
+        .await;
+    }
+
+    pub fn api(&self) -> TwitterApi {
+        TwitterApi {
+            token: Token::Bearer(String::from("test_token")),
+            base_url: self.server.uri(),
+            full_archive_search: false,
+        }
+    }
+
+    /// Returns API credentials which allow full-archive search.
+    pub fn full_archive_api(&self) -> TwitterApi {
+        TwitterApi { full_archive_search: true, ..self.api() }
+    }
+}
+
+/// Returns (file stem, content) pairs of JSON fixtures in the directory.
+fn fixture_files(dir: &str) -> Vec<(String, String)> {
+    fs::read_dir(fixtures_path(dir)).unwrap()
+    .filter_map(|entry| entry.ok().map(|e| e.path()))
+    .filter(|path| path.extension().map(|e| e == "json").unwrap_or(false))
+    .map(|path| {
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        (name, fs::read_to_string(&path).unwrap())
+    })
+    .collect::<Vec<_>>()
+}
+
+/// Answers tweet lookups the way v2 API does: `includes.media` has media of the tweet only with the
+/// `attachments.media_keys` expansion and media of referenced tweets only with `referenced_tweets.id.attachments.media_keys`.
+struct TweetLookupResponder(Value);
+
+impl Respond for TweetLookupResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let expansions = request.url.query_pairs()
+        .find(|(key, _)| key == "expansions")
+        .map(|(_, value)| value.split(',').map(String::from).collect::<Vec<_>>())
+        .unwrap_or_default();
+        let media_keys = |tweet: &Value| tweet["attachments"]["media_keys"].as_array().cloned().unwrap_or_default();
+
+        let mut body = self.0.clone();
+        let mut requested_keys = vec![];
+        if expansions.iter().any(|expansion| expansion == "attachments.media_keys") {
+            requested_keys.extend(media_keys(&body["data"]));
+        }
+        if expansions.iter().any(|expansion| expansion == "referenced_tweets.id.attachments.media_keys") {
+            let tweets = body["includes"]["tweets"].as_array().cloned().unwrap_or_default();
+            requested_keys.extend(tweets.iter().flat_map(media_keys));
+        }
+        if let Some(includes) = body["includes"].as_object_mut() {
+            let media = includes.remove("media").and_then(|media| media.as_array().cloned()).unwrap_or_default()
+            .into_iter()
+            .filter(|media| requested_keys.contains(&media["media_key"]))
+            .collect::<Vec<_>>();
+            if !media.is_empty() {
+                includes.insert(String::from("media"), Value::Array(media));
+            }
+        }
+        ResponseTemplate::new(200).set_body_json(body)
+    }
+}
+
+/// Matches conversation search requests by the conversation ID in the `query` parameter.
+struct ConversationQuery(String);
+
+impl Match for ConversationQuery {
+    fn matches(&self, request: &Request) -> bool {
+        let expected = format!("conversation_id:{} ", self.0);
+        request.url.query_pairs().any(|(key, value)| key == "query" && value.starts_with(expected.as_str()))
+    }
+}
+
+pub const CHAT_ID: i64 = 4242;
+pub const GROUP_CHAT_ID: i64 = -4343;
+pub const ADMIN_ID: i64 = 4444;
+pub const BOT_NAME: &str = "twt2tgbot";
+
+/// In-process stand-in for Telegram Bot API. Every request is recorded and answered with a stub result.
+pub struct TelegramMock {
+    pub server: MockServer
+}
+
+/// A recorded Bot API call with parameters decoded from either a JSON or a multipart body.
+#[derive(Debug)]
+pub struct TelegramCall {
+    pub method: String,
+    pub params: Value,
+}
+
+impl TelegramCall {
+    pub fn str(&self, key: &str) -> &str {
+        self.params[key].as_str().unwrap_or_else(|| panic!("Missed {} in {}: {}", key, self.method, self.params))
+    }
+
+    /// Returns content of an uploaded file referenced as `attach://<name>`.
+    pub fn file(&self, key: &str) -> &str {
+        let name = self.str(key).strip_prefix("attach://").unwrap_or_else(|| panic!("{} is not uploaded", key));
+        self.str(name)
+    }
+}
+
+impl TelegramMock {
+    pub async fn start() -> TelegramMock {
+        TelegramMock::start_with(TelegramResponder { reject_video_urls: false }).await
+    }
+
+    /// Starts a server which fails `sendVideo` the way Telegram does when it can't fetch a video by URL.
+    pub async fn start_rejecting_video_urls() -> TelegramMock {
+        TelegramMock::start_with(TelegramResponder { reject_video_urls: true }).await
+    }
+
+    async fn start_with(responder: TelegramResponder) -> TelegramMock {
+        let server = MockServer::start().await;
+        Mock::given(any())
+        .respond_with(responder)
+        .mount(&server)
+        .await;
+        TelegramMock { server }
+    }
+
+    pub fn bot(&self) -> Bot {
+        Bot::new("12345:test_token").set_api_url(Url::parse(self.server.uri().as_str()).unwrap())
+    }
+
+    /// Returns all calls in the order they were received.
+    pub async fn calls(&self) -> Vec<TelegramCall> {
+        self.server.received_requests().await.unwrap_or(vec![]).iter()
+        .map(|request| TelegramCall { method: method_name(request), params: request_params(request) })
+        .collect::<Vec<_>>()
+    }
+
+    pub async fn methods(&self) -> Vec<String> {
+        self.calls().await.into_iter().map(|call| call.method).collect::<Vec<_>>()
+    }
+}
+
+/// Returns Bot API method name in the documented camel case, e.g. `sendMessage`.
+fn method_name(request: &Request) -> String {
+    let name = request.url.path_segments().and_then(|mut s| s.next_back()).unwrap_or("").to_string();
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => name
+    }
+}
+
+fn request_params(request: &Request) -> Value {
+    let content_type = request.headers.iter()
+    .find(|(name, _)| name.as_str().eq_ignore_ascii_case("content-type"))
+    .map(|(_, values)| values.last().as_str().to_string())
+    .unwrap_or(String::from(""));
+    if let Some(boundary) = content_type.split("boundary=").nth(1) {
+        return multipart_params(&String::from_utf8_lossy(&request.body), boundary.trim_matches('"'));
+    }
+    serde_json::from_slice(&request.body).unwrap_or(Value::Null)
+}
+
+/// Decodes text fields of a multipart form. Fields containing JSON (e.g. `media` or `reply_markup`) are parsed.
+fn multipart_params(body: &str, boundary: &str) -> Value {
+    let mut params = serde_json::Map::new();
+    for part in body.split(format!("--{}", boundary).as_str()) {
+        let (headers, value) = match part.split_once("\r\n\r\n") {
+            Some(split) => split,
+            None => continue
+        };
+        let name = headers.split("name=\"").nth(1).and_then(|s| s.split('"').next());
+        if let Some(name) = name {
+            let value = value.strip_suffix("\r\n").unwrap_or(value);
+            let value = serde_json::from_str::<Value>(value)
+            .ok()
+            .filter(|v| v.is_object() || v.is_array())
+            .unwrap_or(Value::String(String::from(value)));
+            params.insert(String::from(name), value);
+        }
+    }
+    Value::Object(params)
+}
+
+struct TelegramResponder {
+    reject_video_urls: bool
+}
+
+impl Respond for TelegramResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let method = method_name(request);
+        let is_video_upload = String::from_utf8_lossy(&request.body).contains("name=\"video\"; filename=");
+        if self.reject_video_urls && method == "sendVideo" && !is_video_upload {
+            return ResponseTemplate::new(400).set_body_json(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request: failed to get HTTP URL content"
+            }));
+        }
+        if is_document_url(method.as_str(), &request_params(request)) {
+            return ResponseTemplate::new(400).set_body_json(json!({
+                "ok": false,
+                "error_code": 400,
+                "description": "Bad Request: wrong type of the web page content"
+            }));
+        }
+
+        let result = match method.as_str() {
+            "sendMediaGroup" => json!([stub_message(1), stub_message(2)]),
+            "getChatMember" => chat_member_json(request_params(request)["user_id"].as_i64().unwrap_or(0)),
+            "answerInlineQuery" | "answerCallbackQuery" => json!(true),
+            _ => stub_message(1)
+        };
+        ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "result": result }))
+    }
+}
+
+/// Telegram fetches only GIF, PDF and ZIP documents by URL. Fixture images are JPEGs, so every document URL is rejected.
+fn is_document_url(method: &str, params: &Value) -> bool {
+    let is_url = |value: &Value| value.as_str().map(|s| s.starts_with("http")).unwrap_or(false);
+    match method {
+        "sendDocument" => is_url(&params["document"]),
+        "sendMediaGroup" => params["media"].as_array().map(|media| {
+            media.iter().any(|item| item["type"] == json!("document") && is_url(&item["media"]))
+        }).unwrap_or(false),
+        _ => false
+    }
+}
+
+/// Stand-in for `pbs.twimg.com` serving a stub JPEG at any `*.jpg` path.
+pub async fn image_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+    .and(path_regex(r"\.jpg$"))
+    .respond_with(ResponseTemplate::new(200).set_body_raw(vec![b'i'; 1024], "image/jpeg"))
+    .mount(&server)
+    .await;
+    server
+}
+
+/// Points image URLs of the fixture tweet to the server, keeping their paths.
+pub fn serve_images(source: &mut FixtureTweetSource, tweet_id: u64, server: &MockServer) {
+    let tweet = source.tweets.get_mut(&tweet_id).unwrap();
+    let media = tweet.extended_entities.iter_mut().flat_map(|entities| entities.media.iter_mut());
+    for entity in media.chain(tweet.entities.media.iter_mut().flatten()) {
+        let url = Url::parse(entity.media_url_https.as_str()).unwrap();
+        entity.media_url_https = format!("{}{}", server.uri(), url.path());
+    }
+}
+
+fn stub_message(id: i64) -> Value {
+    json!({
+        "message_id": id,
+        "date": 1667487612,
+        "chat": { "id": CHAT_ID, "type": "private", "first_name": "Test" },
+        "text": "stub"
+    })
+}
+
+/// Returns an administrator for `ADMIN_ID` and a regular member for other users.
+fn chat_member_json(user_id: i64) -> Value {
+    let user = json!({ "id": user_id, "is_bot": false, "first_name": "Test" });
+    match user_id == ADMIN_ID {
+        true => json!({
+            "status": "administrator",
+            "user": user,
+            "can_be_edited": false,
+            "is_anonymous": false,
+            "can_manage_chat": true,
+            "can_change_info": true,
+            "can_delete_messages": true,
+            "can_manage_video_chats": true,
+            "can_invite_users": true,
+            "can_restrict_members": true,
+            "can_promote_members": false
+        }),
+        false => json!({ "status": "member", "user": user })
+    }
+}
+
+pub fn user_json() -> Value {
+    json!({ "id": CHAT_ID, "is_bot": false, "first_name": "Test", "username": "test_user" })
+}
+
+/// Returns a message from the user in a group chat.
+pub fn group_message_json(text: &str, user_id: i64) -> Value {
+    json!({
+        "message_id": 100,
+        "date": 1667487612,
+        "chat": { "id": GROUP_CHAT_ID, "type": "group", "title": "Rustaceans" },
+        "from": { "id": user_id, "is_bot": false, "first_name": "Test" },
+        "text": text
+    })
+}
+
+pub fn text_message_json(text: &str) -> Value {
+    json!({
+        "message_id": 100,
+        "date": 1667487612,
+        "chat": { "id": CHAT_ID, "type": "private", "first_name": "Test" },
+        "from": user_json(),
+        "text": text
+    })
+}
@@ -0,0 +1,645 @@
+mod common;
+
+use std::sync::Arc;
+
+use bot::callback_query_processor::CallbackQueryProcessor;
+use bot::chat_settings::{ChatSettingsStore, SensitiveMedia};
+use bot::fixture_tweet_source::FixtureTweetSource;
+use bot::inline_query_processor::InlineQueryProcessor;
+use bot::text_message_processor::TextMessageProcessor;
+use bot::twitter_v2_source::TwitterV2Source;
+use bot::update_processor::UpdateProcessor;
+use serde_json::json;
+use teloxide::types::{CallbackQuery, ChatId, InlineQuery, Message};
+
+use common::{fixtures_path, group_message_json, image_server, serve_images, text_message_json, user_json, TelegramMock, TwitterMock, ADMIN_ID, BOT_NAME, CHAT_ID, GROUP_CHAT_ID};
+
+const HEAD_TEXT: &str = "<b>Rust Language</b> (@rustlang) · 3 Nov 2022\n\nAnnouncing Rust 1.65.0! 🦀\n\nGeneric associated types, let-else statements and more. A thread 🧵";
+const PHOTO_TEXT: &str = "<b>Rust Language</b> (@rustlang) · 3 Nov 2022\n\nGeneric associated types (GATs) let you have generics on associated types.\n\n🖼 Image: A trait with a generic associated type";
+const VIDEO_TEXT: &str = "<b>Rust Language</b> (@rustlang) · 3 Nov 2022\n\nlet-else statements let you bind on a pattern or diverge. Watch a quick demo:";
+const HEAD_FOOTER: &str = "@rustlang · 3 Nov 2022, 15:00 UTC · <a href=\"https://twitter.com/rustlang/status/1600000000000000001\">Open tweet</a>";
+const QUOTE_TEXT: &str = "<b>This Week in Rust</b> (@ThisWeekInRust) · 8 Aug 2022\n\nPhotos from RustConf are up. See you next year!\n\n┃ <b>Rust Language</b> (@rustlang) · 6 Aug 2022\n┃ RustConf 2022 was amazing &amp; we can't wait for next year! Thanks to everyone who joined us in Portland";
+const GALLERY_TEXT: &str = "<b>Rust Language</b> (@rustlang) · 6 Aug 2022\n\nRustConf 2022 was amazing &amp; we can't wait for next year! Thanks to everyone who joined us in Portland";
+
+fn source() -> FixtureTweetSource {
+    FixtureTweetSource::from_dir(fixtures_path("twitter/v1")).unwrap()
+}
+
+fn settings() -> Arc<ChatSettingsStore> {
+    Arc::new(ChatSettingsStore::default())
+}
+
+async fn send_text(telegram: &TelegramMock, text: &str) {
+    send_text_with_settings(telegram, text, settings()).await;
+}
+
+async fn send_text_with_settings(telegram: &TelegramMock, text: &str, settings: Arc<ChatSettingsStore>) {
+    let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings, bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &source()).await.unwrap();
+}
+
+#[tokio::test]
+async fn sends_text_reply_with_unroll_button() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000001").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage"]);
+    assert_eq!(calls[0].params["chat_id"], json!(CHAT_ID));
+    assert_eq!(calls[0].str("text"), HEAD_TEXT);
+    assert_eq!(calls[0].str("parse_mode"), "HTML");
+    assert_eq!(calls[0].params["reply_markup"]["inline_keyboard"][0][0]["callback_data"], json!("unroll_1600000000000000001"));
+}
+
+#[tokio::test]
+async fn sends_single_image_reply() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://twitter.com/rustlang/status/1600000000000000002?s=20").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto"]);
+    assert_eq!(calls[0].str("photo"), "https://pbs.twimg.com/media/FhQ9nXpWAAEw3Ls.jpg");
+    assert_eq!(calls[0].str("caption"), PHOTO_TEXT);
+    assert_eq!(calls[0].str("parse_mode"), "HTML");
+    let keyboard = &calls[0].params["reply_markup"]["inline_keyboard"];
+    assert_eq!(keyboard[0][0]["callback_data"], json!("unroll_1600000000000000002"));
+    assert_eq!(keyboard[0][1]["callback_data"], json!("unroll_from_1600000000000000002"));
+    assert_eq!(keyboard[1][0]["callback_data"], json!("original_1600000000000000002"));
+}
+
+#[tokio::test]
+async fn sends_long_caption_as_follow_up_message() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://twitter.com/rustlang/status/1600000000000000100").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto", "sendMessage"]);
+    assert_eq!(calls[0].str("caption"), "<b>Rust Language</b> (@rustlang) · 15 Nov 2022\n\nHow much faster did the compiler get this year? Here is every benchmark from the perf suite, January to November 📈");
+    assert!(calls[0].params["reply_markup"].is_object());
+    assert!(calls[1].str("text").starts_with("🖼 Image: Line chart of compile times"));
+    assert_eq!(calls[1].str("parse_mode"), "HTML");
+}
+
+#[tokio::test]
+async fn sends_original_images_when_enabled_in_chat() {
+    let telegram = TelegramMock::start().await;
+    let images = image_server().await;
+    let mut source = source();
+    serve_images(&mut source, 1600000000000000002, &images);
+    let settings = settings();
+    for text in ["/settings original_images on", "https://x.com/rustlang/status/1600000000000000002"] {
+        let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+        let processor = TextMessageProcessor { message, text: String::from(text), settings: settings.clone(), bot_name: String::from(BOT_NAME) };
+        processor.process(telegram.bot(), &source).await.unwrap();
+    }
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendPhoto", "sendDocument"]);
+    assert_eq!(calls[1].params["reply_markup"]["inline_keyboard"].as_array().unwrap().len(), 1);
+    assert_eq!(calls[2].file("document").len(), 1024);
+    let requests = images.received_requests().await.unwrap();
+    assert_eq!(requests[0].url.path(), "/media/FhQ9nXpWAAEw3Ls.jpg");
+    assert_eq!(requests[0].url.query(), Some("name=orig"));
+}
+
+#[tokio::test]
+async fn sends_single_video_reply() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000003").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendVideo"]);
+    assert!(calls[0].str("video").contains("/vid/1280x720/"));
+    assert_eq!(calls[0].str("caption"), VIDEO_TEXT);
+}
+
+#[tokio::test]
+async fn sends_video_in_chat_quality() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_text_with_settings(&telegram, "/settings video_quality 360p", settings.clone()).await;
+    send_text_with_settings(&telegram, "https://x.com/rustlang/status/1600000000000000003", settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendVideo"]);
+    assert!(calls[0].str("text").starts_with("<b>Settings updated</b>\n\nvideo_quality: 360p"));
+    assert!(calls[1].str("video").contains("/vid/640x360/"));
+}
+
+#[tokio::test]
+async fn sends_footer_when_enabled_in_chat() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_text_with_settings(&telegram, "/settings footer on", settings.clone()).await;
+    send_text_with_settings(&telegram, "https://x.com/rustlang/status/1600000000000000001", settings.clone()).await;
+    send_text_with_settings(&telegram, "/start unroll_1600000000000000003", settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendMessage", "sendMessage", "sendPhoto", "sendVideo"]);
+    assert_eq!(calls[1].str("text"), format!("{}\n\n{}", HEAD_TEXT, HEAD_FOOTER));
+    assert!(calls[4].str("caption").ends_with(HEAD_FOOTER));
+}
+
+#[tokio::test]
+async fn sends_metrics_when_enabled_in_chat() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_text_with_settings(&telegram, "/settings metrics on", settings.clone()).await;
+    send_text_with_settings(&telegram, "https://x.com/rustlang/status/1600000000000000001", settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(calls[1].str("text"), format!("{}\n\n❤️ 5.2K  🔁 1.4K", HEAD_TEXT));
+}
+
+#[tokio::test]
+async fn rejects_unknown_setting() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "/settings video_quality 4k").await;
+
+    let calls = telegram.calls().await;
+    assert!(calls[0].str("text").starts_with("<b>Unknown setting or value</b>"));
+}
+
+async fn send_group_text(telegram: &TelegramMock, text: &str, user_id: i64, settings: Arc<ChatSettingsStore>) {
+    let message: Message = serde_json::from_value(group_message_json(text, user_id)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings, bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &source()).await.unwrap();
+}
+
+#[tokio::test]
+async fn allows_only_administrators_to_change_group_settings() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_group_text(&telegram, "/settings sensitive_media show", CHAT_ID, settings.clone()).await;
+    send_group_text(&telegram, "/settings", CHAT_ID, settings.clone()).await;
+    send_group_text(&telegram, "/settings@twt2tgbot sensitive_media block", ADMIN_ID, settings.clone()).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["getChatMember", "sendMessage", "sendMessage", "getChatMember", "sendMessage"]);
+    assert!(calls[1].str("text").starts_with("<b>Only administrators can change settings</b>"));
+    assert!(calls[2].str("text").starts_with("<b>Settings</b>"));
+    assert!(calls[4].str("text").starts_with("<b>Settings updated</b>"));
+    assert_eq!(settings.get(ChatId(GROUP_CHAT_ID)).sensitive_media, SensitiveMedia::Block);
+}
+
+#[tokio::test]
+async fn ignores_commands_for_other_bots() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "/settings@otherbot footer on").await;
+    send_text(&telegram, "/settingsfooter on").await;
+
+    assert!(telegram.methods().await.is_empty());
+}
+
+#[tokio::test]
+async fn sends_media_group_reply() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000010").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup", "sendMessage"]);
+    let media = calls[0].params["media"].as_array().unwrap();
+    assert_eq!(media.len(), 2);
+    assert_eq!(media[0]["type"], json!("photo"));
+    assert_eq!(media[1]["media"], json!("https://pbs.twimg.com/media/FbV1YwAXkAIvq0k.jpg"));
+    assert_eq!(calls[1].str("text"), GALLERY_TEXT);
+}
+
+#[tokio::test]
+async fn sends_quote_tweet_with_quoted_media() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000020").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup", "sendMessage"]);
+    assert_eq!(calls[0].params["media"].as_array().unwrap().len(), 2);
+    assert_eq!(calls[1].str("text"), QUOTE_TEXT);
+}
+
+#[tokio::test]
+async fn sends_retweeted_tweet() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000030").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup", "sendMessage"]);
+    assert_eq!(calls[1].str("text"), format!("🔁 <i>Retweeted by This Week in Rust</i>\n{}", GALLERY_TEXT));
+}
+
+#[tokio::test]
+async fn sends_tweet_entities_as_links() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000050").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto"]);
+    assert_eq!(calls[0].str("caption"), "<b>This Week in Rust</b> (@ThisWeekInRust) · 10 Aug 2022\n\nBig thanks to <a href=\"https://twitter.com/rustlang\">@rustlang</a> &amp; the <a href=\"https://twitter.com/hashtag/RustConf\">#RustConf</a> team 🦀 Slides: <a href=\"https://rustconf.com/slides?year=2022\">rustconf.com/slides?year=20…</a>\n\n🖼 Image: Group photo of the RustConf 2022 crowd in Portland");
+}
+
+#[tokio::test]
+async fn sends_every_linked_tweet() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000002 and https://x.com/rustlang/status/1600000000000000001").await;
+
+    assert_eq!(telegram.methods().await, vec!["sendPhoto", "sendMessage"]);
+}
+
+#[tokio::test]
+async fn sends_thread_reply() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "/start unroll_1600000000000000003").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendPhoto", "sendVideo"]);
+    assert_eq!(calls[0].str("text"), HEAD_TEXT);
+    assert_eq!(calls[1].str("caption"), "Generic associated types (GATs) let you have generics on associated types.\n\n🖼 Image: A trait with a generic associated type");
+    assert_eq!(calls[2].str("caption"), "let-else statements let you bind on a pattern or diverge. Watch a quick demo:");
+}
+
+#[tokio::test]
+async fn answers_inline_query_with_media() {
+    let telegram = TelegramMock::start().await;
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_1",
+        "from": user_json(),
+        "query": "https://x.com/rustlang/status/1600000000000000010",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["answerInlineQuery"]);
+    assert_eq!(calls[0].str("inline_query_id"), "query_1");
+    assert_eq!(calls[0].str("switch_pm_text"), "All Media");
+    let results = calls[0].params["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["type"], json!("photo"));
+    assert_eq!(results[0]["caption"], json!(GALLERY_TEXT));
+    assert_eq!(results[0]["parse_mode"], json!("HTML"));
+}
+
+#[tokio::test]
+async fn sends_animated_gif_as_animation() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000080").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendAnimation"]);
+    assert_eq!(calls[0].str("animation"), "https://video.twimg.com/tweet_video/FhFeRrIsAaBbCcD.mp4");
+    assert_eq!(calls[0].str("caption"), "<b>Rust Language</b> (@rustlang) · 6 Nov 2022\n\nFerris approves of the new release 🦀");
+}
+
+#[tokio::test]
+async fn answers_inline_query_with_animated_gif() {
+    let telegram = TelegramMock::start().await;
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_3",
+        "from": user_json(),
+        "query": "https://x.com/rustlang/status/1600000000000000080",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    let results = calls[0].params["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["type"], json!("mpeg4_gif"));
+    assert_eq!(results[0]["mpeg4_url"], json!("https://video.twimg.com/tweet_video/FhFeRrIsAaBbCcD.mp4"));
+}
+
+#[tokio::test]
+async fn answers_inline_query_with_quote_tweet() {
+    let telegram = TelegramMock::start().await;
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_1",
+        "from": user_json(),
+        "query": "https://x.com/ThisWeekInRust/status/1600000000000000020",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    let results = calls[0].params["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[1]["photo_url"], json!("https://pbs.twimg.com/media/FbV1YwAXkAIvq0k.jpg"));
+    assert_eq!(results[1]["caption"], json!(QUOTE_TEXT));
+}
+
+#[tokio::test]
+async fn answers_inline_query_with_text() {
+    let telegram = TelegramMock::start().await;
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_2",
+        "from": user_json(),
+        "query": "https://x.com/rustlang/status/1600000000000000001",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    let results = calls[0].params["results"].as_array().unwrap();
+    assert_eq!(results[0]["type"], json!("article"));
+    assert_eq!(results[0]["input_message_content"]["message_text"], json!(HEAD_TEXT));
+    assert_eq!(calls[0].str("switch_pm_parameter"), "unroll_1600000000000000001");
+}
+
+#[tokio::test]
+async fn keeps_inline_result_title_unescaped() {
+    let telegram = TelegramMock::start().await;
+    let mut source = source();
+    let tweet = source.tweets.get_mut(&1600000000000000001).unwrap();
+    tweet.user.as_mut().unwrap().name = String::from("rust-lang.org_team");
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_2",
+        "from": user_json(),
+        "query": "https://x.com/rustlang/status/1600000000000000001",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source).await.unwrap();
+
+    let calls = telegram.calls().await;
+    let results = calls[0].params["results"].as_array().unwrap();
+    assert_eq!(results[0]["title"], json!("rust-lang.org_team"));
+}
+
+const POLL_TEXT: &str = "<b>Rust Language</b> (@rustlang)\n\nWhich Rust 1.65 feature are you most excited about?\n\n📊 2.7K votes · Final results\n▫️ GATs — 56%\n▫️ let-else — 36%\n▫️ Labeled breaks — 5%\n▫️ Split debuginfo — 2%";
+
+async fn send_poll_tweet(telegram: &TelegramMock, settings: Arc<ChatSettingsStore>) {
+    let twitter = TwitterMock::start().await;
+    let text = "https://x.com/rustlang/status/1600000000000000060";
+    let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings, bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &TwitterV2Source { api: twitter.api() }).await.unwrap();
+}
+
+#[tokio::test]
+async fn renders_poll_results() {
+    let telegram = TelegramMock::start().await;
+    send_poll_tweet(&telegram, settings()).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage"]);
+    assert_eq!(calls[0].str("text"), POLL_TEXT);
+}
+
+#[tokio::test]
+async fn sends_native_poll_in_direct_messages() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    settings.set(ChatId(CHAT_ID), "native_polls", "on").unwrap();
+    send_poll_tweet(&telegram, settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendPoll"]);
+    assert_eq!(calls[1].str("question"), "Which Rust 1.65 feature are you most excited about?");
+    assert_eq!(calls[1].params["options"], json!(["GATs", "let-else", "Labeled breaks", "Split debuginfo"]));
+    assert_eq!(calls[1].params["is_anonymous"], json!(true));
+    assert_eq!(calls[1].params["is_closed"], json!(true));
+}
+
+#[tokio::test]
+async fn previews_shared_link() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/rustlang/status/1600000000000000070").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage"]);
+    assert!(calls[0].str("text").starts_with("<a href=\"https://blog.rust-lang.org/2022/11/03/Rust-1.65.0.html\">\u{200B}</a><b>Rust Language</b>"));
+    assert_eq!(calls[0].params["disable_web_page_preview"], json!(false));
+}
+
+#[tokio::test]
+async fn sends_long_note_tweet_in_chunks() {
+    let telegram = TelegramMock::start().await;
+    let twitter = TwitterMock::start().await;
+    let text = "https://x.com/rustlang/status/1600000000000000130";
+    let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings: settings(), bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &TwitterV2Source { api: twitter.api() }).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendMessage"]);
+    assert!(calls[0].str("text").starts_with("<b>Rust Language</b> (@rustlang) · 20 Dec 2022\n\nThe State of Rust 2022"));
+    assert!(calls[0].str("text").chars().count() <= 4096);
+    assert!(calls[1].str("text").ends_with("Thanks to everyone who tested nightly builds and reported issues along the way!"));
+}
+
+#[tokio::test]
+async fn cuts_long_note_tweet_in_inline_article() {
+    let telegram = TelegramMock::start().await;
+    let twitter = TwitterMock::start().await;
+    let query: InlineQuery = serde_json::from_value(json!({
+        "id": "query_3",
+        "from": user_json(),
+        "query": "https://x.com/rustlang/status/1600000000000000130",
+        "offset": ""
+    })).unwrap();
+    InlineQueryProcessor { query, settings: settings() }.process(telegram.bot(), &TwitterV2Source { api: twitter.api() }).await.unwrap();
+
+    let calls = telegram.calls().await;
+    let text = calls[0].params["results"][0]["input_message_content"]["message_text"].as_str().unwrap();
+    assert!(text.chars().count() <= 4096);
+    assert!(text.ends_with('…'));
+}
+
+#[tokio::test]
+async fn sends_link_card_as_photo() {
+    let telegram = TelegramMock::start().await;
+    let twitter = TwitterMock::start().await;
+    let text = "https://x.com/rustlang/status/1600000000000000070";
+    let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings: settings(), bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &TwitterV2Source { api: twitter.api() }).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto"]);
+    assert_eq!(calls[0].str("photo"), "https://pbs.twimg.com/news_img/1588000000000000070/BlOgCaRd?format=jpg&name=orig");
+    assert!(calls[0].str("caption").ends_with("\n\n🔗 <a href=\"https://blog.rust-lang.org/2022/11/03/Rust-1.65.0.html\"><b>Announcing Rust 1.65.0 | Rust Blog</b></a>\nEmpowering everyone to build reliable and efficient software."));
+}
+
+#[tokio::test]
+async fn unrolls_thread_from_callback() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_1",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "unroll_1600000000000000001",
+        "message": text_message_json("stub")
+    })).unwrap();
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["editMessageText", "sendPhoto", "sendVideo"]);
+    assert_eq!(calls[0].params["message_id"], json!(100));
+    assert_eq!(calls[0].str("text"), HEAD_TEXT);
+}
+
+#[tokio::test]
+async fn unrolls_thread_from_tweet_in_the_middle() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_1",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "unroll_from_1600000000000000002",
+        "message": text_message_json("stub")
+    })).unwrap();
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["editMessageText", "sendVideo"]);
+    assert_eq!(calls[0].str("text"), PHOTO_TEXT);
+}
+
+#[tokio::test]
+async fn tells_that_later_tweets_of_old_thread_are_unknown() {
+    let telegram = TelegramMock::start().await;
+    let twitter = TwitterMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_1",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "unroll_1600000000000000110",
+        "message": text_message_json("stub")
+    })).unwrap();
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &TwitterV2Source { api: twitter.api() }).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["editMessageText"]);
+    assert_eq!(calls[0].str("text"), "<b>Rust Language</b> (@rustlang) · 21 Oct 2021\n\nRust 1.56.0 and Rust 2021 are here! 🎉 A thread on what the new edition brings 🧵\n\n<i>⚠️ Only this tweet and earlier ones of the thread could be loaded</i>");
+}
+
+#[tokio::test]
+async fn rejects_unknown_callback_data() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_4",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "unknown",
+        "message": text_message_json("stub")
+    })).unwrap();
+
+    assert!(CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.is_err());
+    assert!(telegram.methods().await.is_empty());
+}
+
+#[tokio::test]
+async fn rejects_invalid_unroll_link() {
+    let telegram = TelegramMock::start().await;
+    let message: Message = serde_json::from_value(text_message_json("/start unroll_from_latest")).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from("/start unroll_from_latest"), settings: settings(), bot_name: String::from(BOT_NAME) };
+
+    assert!(processor.process(telegram.bot(), &source()).await.is_err());
+    assert!(telegram.methods().await.is_empty());
+}
+
+const SENSITIVE_HEADER: &str = "<b>This Week in Rust</b> (@ThisWeekInRust) · 11 Aug 2022";
+
+#[tokio::test]
+async fn hides_sensitive_media_behind_spoiler() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000090").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto"]);
+    assert_eq!(calls[0].str("has_spoiler"), "true");
+    assert_eq!(calls[0].str("caption"), format!("{}\n\n<tg-spoiler>Spoilers for the RustConf closing keynote 👀</tg-spoiler>", SENSITIVE_HEADER));
+}
+
+#[tokio::test]
+async fn hides_alt_text_of_sensitive_image() {
+    let telegram = TelegramMock::start().await;
+    send_text(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000120").await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendPhoto"]);
+    assert_eq!(calls[0].str("has_spoiler"), "true");
+    assert_eq!(calls[0].str("caption"), "<b>This Week in Rust</b> (@ThisWeekInRust) · 12 Aug 2022\n\n<tg-spoiler>The surprise from the closing keynote, in case you missed it 🙈\n\n🖼 Image: Slide announcing that Ferris gets a plush edition</tg-spoiler>");
+}
+
+#[tokio::test]
+async fn blocks_sensitive_media_when_configured_in_chat() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_text_with_settings(&telegram, "/settings sensitive_media block", settings.clone()).await;
+    send_text_with_settings(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000090", settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendMessage"]);
+    assert_eq!(calls[1].str("text"), format!("{}\n\n<i>⚠️ Sensitive content is hidden in this chat</i>", SENSITIVE_HEADER));
+}
+
+#[tokio::test]
+async fn passes_sensitive_media_through_when_configured_in_chat() {
+    let telegram = TelegramMock::start().await;
+    let settings = settings();
+    send_text_with_settings(&telegram, "/settings sensitive_media show", settings.clone()).await;
+    send_text_with_settings(&telegram, "https://x.com/ThisWeekInRust/status/1600000000000000090", settings).await;
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMessage", "sendPhoto"]);
+    assert_eq!(calls[1].str("has_spoiler"), "false");
+    assert_eq!(calls[1].str("caption"), format!("{}\n\nSpoilers for the RustConf closing keynote 👀", SENSITIVE_HEADER));
+}
+
+#[tokio::test]
+async fn sends_original_images_from_callback() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_3",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "original_1600000000000000010",
+        "message": text_message_json("stub")
+    })).unwrap();
+    let images = image_server().await;
+    let mut source = source();
+    serve_images(&mut source, 1600000000000000010, &images);
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup"]);
+    let media = calls[0].params["media"].as_array().unwrap();
+    assert_eq!(media.len(), 2);
+    assert_eq!(media[0]["type"], json!("document"));
+    assert!(media[0]["media"].as_str().unwrap().starts_with("attach://"));
+    assert_eq!(images.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn sends_all_media_from_callback() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_2",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "1600000000000000010",
+        "message": text_message_json("stub")
+    })).unwrap();
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup", "editMessageCaption"]);
+    assert_eq!(calls[1].str("caption"), GALLERY_TEXT);
+}
+
+#[tokio::test]
+async fn sends_long_caption_of_all_media_as_follow_up_message() {
+    let telegram = TelegramMock::start().await;
+    let query: CallbackQuery = serde_json::from_value(json!({
+        "id": "callback_2",
+        "from": user_json(),
+        "chat_instance": "instance",
+        "data": "1600000000000000100",
+        "message": text_message_json("stub")
+    })).unwrap();
+    CallbackQueryProcessor { query, settings: settings() }.process(telegram.bot(), &source()).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendMediaGroup", "editMessageCaption", "sendMessage"]);
+    assert_eq!(calls[1].str("caption"), "<b>Rust Language</b> (@rustlang) · 15 Nov 2022\n\nHow much faster did the compiler get this year? Here is every benchmark from the perf suite, January to November 📈");
+    assert!(calls[2].str("text").starts_with("🖼 Image: Line chart of compile times"));
+}
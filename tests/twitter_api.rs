@@ -0,0 +1,423 @@
+mod common;
+
+use std::str::FromStr;
+
+use bot::fixture_tweet_source::FixtureTweetSource;
+use bot::parser::{tweet_to_reply, ParsedMedia, Reply};
+use bot::thread_parser::{is_included_in_thread, tweet_to_thread};
+use bot::tweet_source::TweetSource;
+use bot::twitter_v1_source::TwitterV1Source;
+use bot::twitter_v2_source::TwitterV2Source;
+
+use common::{fixtures_path, TwitterMock};
+
+const THREAD_HEAD_ID: u64 = 1600000000000000001;
+const THREAD_PHOTO_ID: u64 = 1600000000000000002;
+const THREAD_VIDEO_ID: u64 = 1600000000000000003;
+const SINGLE_TWEET_ID: u64 = 1600000000000000010;
+const QUOTE_TWEET_ID: u64 = 1600000000000000020;
+const RETWEET_ID: u64 = 1600000000000000030;
+const NOTE_TWEET_ID: u64 = 1600000000000000040;
+const ENTITIES_TWEET_ID: u64 = 1600000000000000050;
+const POLL_TWEET_ID: u64 = 1600000000000000060;
+const LINK_CARD_TWEET_ID: u64 = 1600000000000000070;
+const GIF_TWEET_ID: u64 = 1600000000000000080;
+const SENSITIVE_TWEET_ID: u64 = 1600000000000000090;
+const OLD_THREAD_HEAD_ID: u64 = 1600000000000000110;
+const OLD_THREAD_MIDDLE_ID: u64 = 1600000000000000111;
+const OLD_THREAD_LAST_ID: u64 = 1600000000000000112;
+const GIF_RETWEET_ID: u64 = 1600000000000000140;
+const GIF_URL: &str = "https://video.twimg.com/tweet_video/FhFeRrIsAaBbCcD.mp4";
+const LINK_CARD_URL: &str = "https://blog.rust-lang.org/2022/11/03/Rust-1.65.0.html";
+const ENTITIES_TWEET_TEXT: &str = "Big thanks to @rustlang & the #RustConf team 🦀 Slides: rustconf.com/slides?year=20…";
+const NOTE_TWEET_TEXT: &str = "Rust 1.65.0 is out! Here's a longer summary for those who can't wait for the blog post. Generic associated types (GATs) finally land on stable after more than six years of work. let-else statements make early returns on a failed pattern match concise. break can now leave labeled blocks with a value. Split debug info is enabled by default on Linux, and RLS is deprecated in favour of rust-analyzer. Thanks to all 300+ contributors who made it happen!";
+
+#[tokio::test]
+async fn converts_text_tweet_to_reply() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(THREAD_HEAD_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.user_name, Some(String::from("Rust Language")));
+    assert_eq!(reply.screen_name, Some(String::from("rustlang")));
+    assert!(!reply.verified);
+    assert_eq!(reply.created_at.unwrap().to_rfc3339(), "2022-11-03T15:00:12+00:00");
+    assert_eq!(reply.thumb_url.unwrap().as_str(), "https://pbs.twimg.com/profile_images/1392187446734458882/qsrUdG9N_normal.png");
+    assert_eq!(reply.text, "Announcing Rust 1.65.0! 🦀\n\nGeneric associated types, let-else statements and more. A thread 🧵");
+    assert!(reply.media_entities.is_empty());
+}
+
+#[tokio::test]
+async fn converts_tweet_with_images_to_reply() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(SINGLE_TWEET_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.text, "RustConf 2022 was amazing & we can't wait for next year! Thanks to everyone who joined us in Portland");
+    assert_eq!(reply.media_entities.len(), 2);
+    match &reply.media_entities[1] {
+        ParsedMedia::Image(image) => {
+            assert_eq!(image.url.as_str(), "https://pbs.twimg.com/media/FbV1YwAXkAIvq0k.jpg");
+            assert_eq!((image.width, image.height), (1536, 2048));
+        },
+        _ => panic!("Expected an image")
+    }
+}
+
+#[tokio::test]
+async fn converts_quote_tweet_to_reply() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(QUOTE_TWEET_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.user_name, Some(String::from("This Week in Rust")));
+    assert_eq!(reply.text, "Photos from RustConf are up. See you next year!");
+    assert!(reply.media_entities.is_empty());
+    let quoted = reply.quoted.as_ref().expect("Expected a quoted tweet");
+    assert_eq!(quoted.user_name, Some(String::from("Rust Language")));
+    assert_eq!(quoted.media_entities.len(), 2);
+    assert_eq!(reply.displayed_media().len(), 2);
+}
+
+#[tokio::test]
+async fn converts_quote_tweet_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(QUOTE_TWEET_ID).await.unwrap();
+
+    assert_eq!(reply.text, "Photos from RustConf are up. See you next year!");
+    let quoted = reply.quoted.as_ref().expect("Expected a quoted tweet");
+    assert_eq!(quoted.user_name, Some(String::from("Rust Language")));
+    assert_eq!(quoted.text, "RustConf 2022 was amazing & we can't wait for next year! Thanks to everyone who joined us in Portland");
+    assert_eq!(quoted.media_entities.len(), 2);
+}
+
+#[tokio::test]
+async fn unwraps_retweet() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(RETWEET_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.retweeted_by, Some(String::from("This Week in Rust")));
+    assert_eq!(reply.user_name, Some(String::from("Rust Language")));
+    assert_eq!(reply.text, "RustConf 2022 was amazing & we can't wait for next year! Thanks to everyone who joined us in Portland");
+    assert_eq!(reply.media_entities.len(), 2);
+}
+
+#[tokio::test]
+async fn unwraps_retweet_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(RETWEET_ID).await.unwrap();
+
+    assert_eq!(reply.retweeted_by, Some(String::from("This Week in Rust")));
+    assert_eq!(reply.user_name, Some(String::from("Rust Language")));
+    assert_eq!(reply.text, "RustConf 2022 was amazing & we can't wait for next year! Thanks to everyone who joined us in Portland");
+    assert_eq!(reply.media_entities.len(), 2);
+}
+
+#[tokio::test]
+async fn unwraps_retweet_of_animation_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(GIF_RETWEET_ID).await.unwrap();
+
+    assert_eq!(reply.retweeted_by, Some(String::from("This Week in Rust")));
+    assert_eq!(reply.user_name, Some(String::from("Rust Language")));
+    assert_eq!(reply.text, "Ferris approves of the new release 🦀");
+    match reply.media_entities.first() {
+        Some(ParsedMedia::Animation(animation)) => assert_eq!(animation.url.as_str(), GIF_URL),
+        _ => panic!("Expected an animation")
+    }
+}
+
+#[tokio::test]
+async fn loads_note_tweet_text() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV1Source { api: mock.api() };
+    let reply = source.reply(NOTE_TWEET_ID).await.unwrap();
+
+    assert_eq!(reply.text, NOTE_TWEET_TEXT);
+}
+
+#[tokio::test]
+async fn loads_note_tweet_text_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(NOTE_TWEET_ID).await.unwrap();
+
+    assert_eq!(reply.text, NOTE_TWEET_TEXT);
+}
+
+fn poll_options(reply: &Reply) -> Vec<(&str, i64)> {
+    reply.poll.as_ref().unwrap().options.iter().map(|option| (option.label.as_str(), option.votes)).collect()
+}
+
+#[tokio::test]
+async fn loads_poll() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV1Source { api: mock.api() };
+    let reply = source.reply(POLL_TWEET_ID).await.unwrap();
+
+    let poll = reply.poll.as_ref().unwrap();
+    assert!(!poll.open);
+    assert_eq!(poll.end_datetime.unwrap().to_rfc3339(), "2022-11-06T12:00:00+00:00");
+    assert_eq!(poll.total_votes(), 2700);
+    assert_eq!(poll_options(&reply), vec![("GATs", 1520), ("let-else", 980), ("Labeled breaks", 140), ("Split debuginfo", 60)]);
+}
+
+#[tokio::test]
+async fn loads_poll_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(POLL_TWEET_ID).await.unwrap();
+
+    assert_eq!(reply.text, "Which Rust 1.65 feature are you most excited about?");
+    assert_eq!(poll_options(&reply), vec![("GATs", 1520), ("let-else", 980), ("Labeled breaks", 140), ("Split debuginfo", 60)]);
+}
+
+#[tokio::test]
+async fn converts_shared_link_to_card() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(LINK_CARD_TWEET_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    let card = reply.displayed_card().unwrap();
+    assert_eq!(card.url.as_str(), LINK_CARD_URL);
+    assert_eq!(card.title, None);
+    assert_eq!(reply.preview_url().unwrap().as_str(), LINK_CARD_URL);
+    assert_eq!(reply.displayed_media().len(), 0);
+}
+
+#[tokio::test]
+async fn loads_link_card() {
+    let mock = TwitterMock::start().await;
+    for reply in [
+        TwitterV1Source { api: mock.api() }.reply(LINK_CARD_TWEET_ID).await.unwrap(),
+        TwitterV2Source { api: mock.api() }.reply(LINK_CARD_TWEET_ID).await.unwrap()
+    ] {
+        let card = reply.displayed_card().unwrap();
+        assert_eq!(card.url.as_str(), LINK_CARD_URL);
+        assert_eq!(card.title, Some(String::from("Announcing Rust 1.65.0 | Rust Blog")));
+        assert_eq!(card.description, Some(String::from("Empowering everyone to build reliable and efficient software.")));
+        assert_eq!(reply.preview_url(), None);
+        match reply.displayed_media()[..] {
+            [ParsedMedia::Image(image)] => assert_eq!(image.url.as_str(), "https://pbs.twimg.com/news_img/1588000000000000070/BlOgCaRd?format=jpg&name=orig"),
+            _ => panic!("Card image isn't displayed")
+        }
+    }
+}
+
+fn linked_parts(reply: &Reply) -> Vec<(&str, &str)> {
+    reply.links.iter().map(|link| (&reply.text[link.range.clone()], link.url.as_str())).collect()
+}
+
+#[tokio::test]
+async fn expands_tweet_entities() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(ENTITIES_TWEET_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.text, ENTITIES_TWEET_TEXT);
+    assert_eq!(linked_parts(&reply), vec![
+        ("@rustlang", "https://twitter.com/rustlang"),
+        ("#RustConf", "https://twitter.com/hashtag/RustConf"),
+        ("rustconf.com/slides?year=20…", "https://rustconf.com/slides?year=2022"),
+    ]);
+}
+
+#[tokio::test]
+async fn expands_tweet_entities_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+    let reply = source.reply(ENTITIES_TWEET_ID).await.unwrap();
+
+    assert_eq!(reply.text, ENTITIES_TWEET_TEXT);
+    assert_eq!(linked_parts(&reply).len(), 3);
+}
+
+#[tokio::test]
+async fn converts_tweet_with_video_to_reply() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(THREAD_VIDEO_ID).await.unwrap();
+    let reply = tweet_to_reply(&tweet).await.unwrap();
+
+    assert_eq!(reply.text, "let-else statements let you bind on a pattern or diverge. Watch a quick demo:");
+    match reply.media_entities.first() {
+        Some(ParsedMedia::Video(video)) => {
+            assert!(video.url.as_str().contains("/vid/1280x720/"));
+            assert_eq!(video.mime_type, mime::Mime::from_str("video/mp4").unwrap());
+        },
+        _ => panic!("Expected a video")
+    }
+}
+
+#[tokio::test]
+async fn keeps_image_alt_text() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(ENTITIES_TWEET_ID).await.unwrap();
+    let v1_reply = tweet_to_reply(&tweet).await.unwrap();
+    let v2_reply = TwitterV2Source { api: mock.api() }.reply(ENTITIES_TWEET_ID).await.unwrap();
+
+    for reply in [v1_reply, v2_reply] {
+        match reply.media_entities.first() {
+            Some(ParsedMedia::Image(image)) => assert_eq!(image.alt_text.as_deref(), Some("Group photo of the RustConf 2022 crowd in Portland")),
+            _ => panic!("Expected an image")
+        }
+    }
+}
+
+#[tokio::test]
+async fn detects_sensitive_tweets() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(SENSITIVE_TWEET_ID).await.unwrap();
+    let v1_reply = tweet_to_reply(&tweet).await.unwrap();
+    let v2_reply = TwitterV2Source { api: mock.api() }.reply(SENSITIVE_TWEET_ID).await.unwrap();
+    assert!(v1_reply.sensitive);
+    assert!(v2_reply.sensitive);
+
+    let tweet = mock.api().show(ENTITIES_TWEET_ID).await.unwrap();
+    assert!(!tweet_to_reply(&tweet).await.unwrap().sensitive);
+}
+
+#[tokio::test]
+async fn converts_animated_gif_to_animation() {
+    let mock = TwitterMock::start().await;
+    let tweet = mock.api().show(GIF_TWEET_ID).await.unwrap();
+    let v1_reply = tweet_to_reply(&tweet).await.unwrap();
+    let v2_reply = TwitterV2Source { api: mock.api() }.reply(GIF_TWEET_ID).await.unwrap();
+
+    for reply in [v1_reply, v2_reply] {
+        assert_eq!(reply.text, "Ferris approves of the new release 🦀");
+        match reply.media_entities.first() {
+            Some(ParsedMedia::Animation(animation)) => assert_eq!(animation.url.as_str(), GIF_URL),
+            _ => panic!("Expected an animation")
+        }
+    }
+}
+
+#[tokio::test]
+async fn converts_tweet_when_v2_lookup_fails() {
+    let mock = TwitterMock::start().await;
+    mock.reject_v2_lookups().await;
+    let source = TwitterV1Source { api: mock.api() };
+
+    let reply = source.reply(POLL_TWEET_ID).await.unwrap();
+    assert_eq!(reply.text, "Which Rust 1.65 feature are you most excited about?");
+    assert!(reply.poll.is_none());
+
+    let note_reply = source.reply(NOTE_TWEET_ID).await.unwrap();
+    assert!(note_reply.text.starts_with("Rust 1.65.0 is out!"));
+    assert_ne!(note_reply.text, NOTE_TWEET_TEXT);
+}
+
+#[tokio::test]
+async fn detects_tweets_included_in_thread() {
+    let mock = TwitterMock::start().await;
+    let api = mock.api();
+
+    assert!(is_included_in_thread(THREAD_VIDEO_ID, &api).await.unwrap());
+    assert!(!is_included_in_thread(SINGLE_TWEET_ID, &api).await.unwrap());
+}
+
+#[tokio::test]
+async fn unrolls_thread() {
+    let mock = TwitterMock::start().await;
+    let api = mock.api();
+    let tweet = api.show(THREAD_VIDEO_ID).await.unwrap();
+    let thread = tweet_to_thread(&tweet, &api).await.unwrap();
+
+    assert_eq!(thread.user_name, Some(String::from("Rust Language")));
+    assert_eq!(thread.entities.len(), 3);
+    assert!(thread.entities[0].text.starts_with("Announcing Rust 1.65.0!"));
+    assert!(thread.entities[0].media_entities.is_empty());
+    assert!(thread.entities[1].text.starts_with("Generic associated types (GATs)"));
+    assert!(matches!(thread.entities[1].media_entities.first(), Some(ParsedMedia::Image(_))));
+    assert!(thread.entities[2].text.starts_with("let-else statements"));
+    assert!(matches!(thread.entities[2].media_entities.first(), Some(ParsedMedia::Video(_))));
+}
+
+#[tokio::test]
+async fn unrolls_thread_from_tweet_in_the_middle() {
+    let mock = TwitterMock::start().await;
+    let api = mock.api();
+    let tweet = api.show(THREAD_PHOTO_ID).await.unwrap();
+    assert!(tweet_to_reply(&tweet).await.unwrap().continues_thread);
+    assert!(!tweet_to_reply(&api.show(THREAD_HEAD_ID).await.unwrap()).await.unwrap().continues_thread);
+
+    let mut thread = tweet_to_thread(&tweet, &api).await.unwrap();
+    assert_eq!(thread.entities.len(), 3);
+    thread.start_from(THREAD_PHOTO_ID).unwrap();
+    let ids = thread.entities.iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![THREAD_PHOTO_ID, THREAD_VIDEO_ID]);
+    assert_eq!(thread.created_at, thread.entities[0].created_at);
+    assert!(thread.start_from(SINGLE_TWEET_ID).is_err());
+}
+
+#[tokio::test]
+async fn unrolls_old_thread_by_reply_chain() {
+    let mock = TwitterMock::start().await;
+    let api = mock.api();
+
+    assert!(is_included_in_thread(OLD_THREAD_LAST_ID, &api).await.unwrap());
+    assert!(!is_included_in_thread(OLD_THREAD_HEAD_ID, &api).await.unwrap());
+
+    let tweet = api.show(OLD_THREAD_LAST_ID).await.unwrap();
+    let v1_thread = tweet_to_thread(&tweet, &api).await.unwrap();
+    let v2_thread = TwitterV2Source { api: api.clone() }.thread(OLD_THREAD_LAST_ID).await.unwrap();
+    for thread in [v1_thread, v2_thread] {
+        let ids = thread.entities.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![OLD_THREAD_HEAD_ID, OLD_THREAD_MIDDLE_ID, OLD_THREAD_LAST_ID]);
+        assert_eq!(thread.screen_name, Some(String::from("rustlang")));
+        assert_eq!(thread.created_at.unwrap().to_rfc3339(), "2021-10-21T16:04:11+00:00");
+        assert!(thread.earlier_tweets_only);
+    }
+}
+
+#[tokio::test]
+async fn unrolls_old_thread_with_full_archive_search() {
+    let mock = TwitterMock::start().await;
+    let api = mock.full_archive_api();
+
+    assert!(is_included_in_thread(OLD_THREAD_HEAD_ID, &api).await.unwrap());
+
+    // The reply chain ends at the middle tweet, so the last one is only found in the archive.
+    let tweet = api.show(OLD_THREAD_MIDDLE_ID).await.unwrap();
+    let v1_thread = tweet_to_thread(&tweet, &api).await.unwrap();
+    let v2_thread = TwitterV2Source { api: api.clone() }.thread(OLD_THREAD_MIDDLE_ID).await.unwrap();
+    for thread in [v1_thread, v2_thread] {
+        let texts = thread.entities.iter().map(|e| e.text.clone()).collect::<Vec<_>>();
+        assert_eq!(texts.len(), 3);
+        assert!(texts[0].starts_with("Rust 1.56.0 and Rust 2021"));
+        assert!(texts[2].starts_with("IntoIterator for arrays"));
+        assert!(!thread.earlier_tweets_only);
+    }
+}
+
+#[tokio::test]
+async fn unrolls_thread_with_v2_source() {
+    let mock = TwitterMock::start().await;
+    let source = TwitterV2Source { api: mock.api() };
+
+    let reply = source.reply(THREAD_PHOTO_ID).await.unwrap();
+    assert_eq!(reply.user_name, Some(String::from("Rust Language")));
+    assert_eq!(reply.media_entities.len(), 1);
+
+    let thread = source.thread(THREAD_PHOTO_ID).await.unwrap();
+    let texts = thread.entities.iter().map(|e| e.text.clone()).collect::<Vec<_>>();
+    assert_eq!(texts.len(), 3);
+    assert!(texts[0].starts_with("Announcing"));
+    assert!(texts[2].starts_with("let-else"));
+}
+
+#[tokio::test]
+async fn unrolls_thread_from_fixtures() {
+    let source = FixtureTweetSource::from_dir(fixtures_path("twitter/v1")).unwrap();
+
+    assert!(source.is_included_in_thread(THREAD_PHOTO_ID).await.unwrap());
+    assert!(!source.is_included_in_thread(SINGLE_TWEET_ID).await.unwrap());
+
+    let thread = source.thread(THREAD_PHOTO_ID).await.unwrap();
+    assert_eq!(thread.entities.len(), 3);
+    assert!(thread.entities[2].text.starts_with("let-else"));
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use bot::chat_settings::ChatSettingsStore;
+use bot::fixture_tweet_source::FixtureTweetSource;
+use bot::parser::{VideoEntity, VideoVariant};
+use bot::text_message_processor::TextMessageProcessor;
+use bot::update_processor::UpdateProcessor;
+use bot::video_uploader::download_video;
+use reqwest::Url;
+use std::fs;
+use std::sync::Arc;
+use teloxide::types::Message;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use common::{fixtures_path, text_message_json, TelegramMock, BOT_NAME};
+
+const VIDEO_TWEET_ID: u64 = 1600000000000000003;
+
+async fn video_server() -> MockServer {
+    let server = MockServer::start().await;
+    for (name, size) in [("high.mp4", 4096), ("medium.mp4", 2048), ("low.mp4", 512)] {
+        Mock::given(method("GET"))
+        .and(path(format!("/{}", name)))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(vec![b'v'; size], "video/mp4"))
+        .mount(&server)
+        .await;
+    }
+    server
+}
+
+fn variant_urls(server: &MockServer) -> Vec<Url> {
+    ["high.mp4", "medium.mp4", "low.mp4"].iter()
+    .map(|name| Url::parse(format!("{}/{}", server.uri(), name).as_str()).unwrap())
+    .collect::<Vec<_>>()
+}
+
+#[tokio::test]
+async fn downloads_highest_variant_within_size_limit() {
+    let server = video_server().await;
+    let urls = variant_urls(&server);
+    let video = VideoEntity {
+        id: String::from("1"),
+        url: urls[0].clone(),
+        thumb_url: urls[0].clone(),
+        mime_type: "video/mp4".parse().unwrap(),
+        width: 1280,
+        height: 720,
+        duration_ms: None,
+        variants: urls.iter().map(|url| VideoVariant { url: url.clone(), bitrate: None }).collect::<Vec<_>>(),
+        has_spoiler: false
+    };
+
+    let path = download_video(&video, 3000).await.unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), 2048);
+
+    assert!(download_video(&video, 100).await.is_err());
+}
+
+#[tokio::test]
+async fn uploads_video_when_telegram_cannot_fetch_url() {
+    let server = video_server().await;
+    let urls = variant_urls(&server);
+    let mut source = FixtureTweetSource::from_dir(fixtures_path("twitter/v1")).unwrap();
+    let tweet = source.tweets.get_mut(&VIDEO_TWEET_ID).unwrap();
+    let info = tweet.extended_entities.as_mut().unwrap().media[0].video_info.as_mut().unwrap();
+    for (variant, url) in info.variants.iter_mut().filter(|v| v.bitrate.is_some()).zip(urls.iter()) {
+        variant.url = url.to_string();
+    }
+
+    let telegram = TelegramMock::start_rejecting_video_urls().await;
+    let text = "https://x.com/rustlang/status/1600000000000000003";
+    let message: Message = serde_json::from_value(text_message_json(text)).unwrap();
+    let processor = TextMessageProcessor { message, text: String::from(text), settings: Arc::new(ChatSettingsStore::default()), bot_name: String::from(BOT_NAME) };
+    processor.process(telegram.bot(), &source).await.unwrap();
+
+    let calls = telegram.calls().await;
+    assert_eq!(telegram.methods().await, vec!["sendVideo", "sendVideo"]);
+    assert_eq!(calls[0].str("video"), urls[0].as_str());
+    assert_eq!(calls[1].file("video").len(), 4096);
+    assert_eq!(calls[1].str("caption"), calls[0].str("caption"));
+}